@@ -2,9 +2,15 @@ use clap::{App, Arg};
 use std::{
     error::Error,
     fs,
-    io::{self, BufRead, BufReader},
+    io::{self, BufRead, BufReader, Write},
 };
 
+mod output;
+mod redact;
+
+use output::AtomicFile;
+use redact::Redactor;
+
 type MyResult<T> = Result<T, Box<dyn Error>>;
 
 #[derive(Debug)]
@@ -12,6 +18,11 @@ pub struct Config {
     files: Vec<String>,
     number_lines: bool,
     number_nonblank_lines: bool,
+    output: Option<String>,
+    append: bool,
+    headers: bool,
+    separator: Option<String>,
+    redactor: Option<Redactor>,
 }
 
 /// Registers the application with clap, and takes in the specified arguments.
@@ -43,13 +54,67 @@ pub fn clap_set() -> MyResult<Config> {
                 .help("Print line numbers on non-blank lines")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("Write to FILE instead of stdout, replacing it atomically")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("append")
+                .long("append")
+                .help("Append to the existing contents of the output FILE")
+                .takes_value(false)
+                .requires("output"),
+        )
+        .arg(
+            Arg::with_name("headers")
+                .long("headers")
+                .help("Precede each file with a header giving the file name")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("separator")
+                .long("separator")
+                .value_name("STRING")
+                .help("Print STRING on its own line between files")
+                .takes_value(true)
+                .allow_hyphen_values(true),
+        )
+        .arg(
+            Arg::with_name("redact")
+                .long("redact")
+                .help("Replace secrets and email addresses with [REDACTED:kind]")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("redact_config")
+                .long("redact-config")
+                .value_name("FILE")
+                .help("Read extra 'kind = regex' redaction patterns from FILE")
+                .takes_value(true)
+                .requires("redact"),
+        )
         .get_matches();
 
+    let redactor = if matches.is_present("redact") {
+        Some(Redactor::new(matches.value_of("redact_config"))?)
+    } else {
+        None
+    };
+
     Ok(Config {
         // Safe operation due to default value
         files: matches.values_of_lossy("file_name").unwrap(),
         number_lines: matches.is_present("numbered"),
         number_nonblank_lines: matches.is_present("numbered_noblank"),
+        output: matches.value_of("output").map(String::from),
+        append: matches.is_present("append"),
+        headers: matches.is_present("headers"),
+        separator: matches.value_of("separator").map(String::from),
+        redactor,
     })
 }
 
@@ -62,41 +127,93 @@ fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
     }
 }
 
-fn print_numbered_line(count: usize, line: &str) {
-    println!("{:>6}\t{}", count, line);
+fn print_numbered_line(out: &mut dyn Write, count: usize, line: &str) -> io::Result<()> {
+    writeln!(out, "{:>6}\t{}", count, line)
 }
 
-/// From an IO buffer, does the corresponding cat print based on the config struct
-fn print_buffer(config: &Config, rd_buffer: Box<dyn BufRead>) {
-    let lines = rd_buffer.lines();
+/// From an IO buffer, does the corresponding cat print based on the config
+/// struct, writing the result into [out]
+fn print_buffer(config: &Config, rd_buffer: Box<dyn BufRead>, out: &mut dyn Write) -> MyResult<()> {
+    let mut redactor = config.redactor.as_ref().map(Redactor::start);
+    let lines = rd_buffer.lines().map(move |line| match &mut redactor {
+        Some(redactor) => line.map(|line| redactor.redact(&line)),
+        None => line,
+    });
     if config.number_lines {
-        lines.fold(1, |acc, ele| {
-            print_numbered_line(acc, ele.expect("Line should pass").as_str());
-            acc + 1
-        });
+        for (count, line) in (1..).zip(lines) {
+            print_numbered_line(out, count, &line?)?;
+        }
     } else if config.number_nonblank_lines {
-        lines.fold(1, |acc, ele| {
-            let cur_line = ele.expect("Line read should be successful");
+        let mut count = 1;
+        for line in lines {
+            let cur_line = line?;
             if cur_line.is_empty() {
-                println!("{}", cur_line);
-                acc
+                writeln!(out, "{}", cur_line)?;
             } else {
-                print_numbered_line(acc, cur_line.as_str());
-                acc + 1
+                print_numbered_line(out, count, &cur_line)?;
+                count += 1;
             }
-        });
+        }
     } else {
-        lines.for_each(|ele| {
-            println!("{}", ele.expect("should see the next line"));
-        });
+        for line in lines {
+            writeln!(out, "{}", line?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Prints the boundary that goes in front of a file: the separator (or a
+/// blank line when only headers are on) if another file came [before], then
+/// the `==> name <==` header.
+fn print_boundary(
+    config: &Config,
+    filename: &str,
+    before: bool,
+    out: &mut dyn Write,
+) -> MyResult<()> {
+    if before {
+        match &config.separator {
+            Some(separator) => writeln!(out, "{}", separator)?,
+            None if config.headers => writeln!(out)?,
+            None => {}
+        }
     }
+    if config.headers {
+        writeln!(out, "==> {} <==", filename)?;
+    }
+    Ok(())
+}
+
+/// Concatenates every input into [dest] through a staging file. Any input
+/// that cannot be read aborts the run, leaving [dest] as it was.
+fn run_to_file(config: &Config, dest: &str) -> MyResult<()> {
+    let mut out = AtomicFile::create(dest, config.append)
+        .map_err(|e| format!("Failed to create {}: {}", dest, e))?;
+    for (i, filename) in config.files.iter().enumerate() {
+        let reader = open(filename).map_err(|e| format!("Failed to open {}: {}", filename, e))?;
+        print_boundary(config, filename, i > 0, &mut out)?;
+        print_buffer(config, reader, &mut out)?;
+    }
+    out.commit()
+        .map_err(|e| format!("Failed to write {}: {}", dest, e))?;
+    Ok(())
 }
 
 pub fn run(config: Config) -> MyResult<()> {
-    let files_iter = config.files.iter();
-    for filename in files_iter {
+    if let Some(dest) = &config.output {
+        return run_to_file(&config, dest);
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut printed_any = false;
+    for filename in &config.files {
         match open(filename) {
-            Ok(x) => print_buffer(&config, x),
+            Ok(x) => {
+                print_boundary(&config, filename, printed_any, &mut out)?;
+                print_buffer(&config, x, &mut out)?;
+                printed_any = true;
+            }
             Err(e) => {
                 eprint!("Failed to open {}: {}", filename, e);
             }
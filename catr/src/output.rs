@@ -0,0 +1,114 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+/// A writer that stages everything in a temporary file next to the
+/// destination, and only replaces the destination once `commit` is called.
+/// Dropping it without committing removes the temporary file and leaves the
+/// destination untouched.
+pub struct AtomicFile {
+    dest: PathBuf,
+    tmp: PathBuf,
+    writer: Option<BufWriter<File>>,
+}
+
+impl AtomicFile {
+    /// Creates the staging file for [dest]. The staging file takes on the
+    /// permissions of the destination if it already exists, and when
+    /// [append] is set it starts with a copy of the destination's contents.
+    /// A destination that is a symlink is followed, so that its target gets
+    /// replaced rather than the link.
+    pub fn create(dest: &str, append: bool) -> io::Result<AtomicFile> {
+        let dest = match fs::canonicalize(dest) {
+            Ok(real) => real,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => PathBuf::from(dest),
+            Err(e) => return Err(e),
+        };
+        let (tmp, file) = create_staging_file(&dest)?;
+        let mut staged = AtomicFile {
+            dest,
+            tmp,
+            writer: Some(BufWriter::new(file)),
+        };
+
+        match fs::metadata(&staged.dest) {
+            Ok(meta) => {
+                fs::set_permissions(&staged.tmp, meta.permissions())?;
+                if append {
+                    io::copy(&mut File::open(&staged.dest)?, &mut staged)?;
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(staged)
+    }
+
+    /// Flushes the staged output to disk and renames it over the destination.
+    /// If any step fails, the staging file is removed when self is dropped.
+    pub fn commit(mut self) -> io::Result<()> {
+        let writer = self.writer();
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(&self.tmp, &self.dest)?;
+        // Only now is there nothing left for Drop to clean up
+        self.writer = None;
+        Ok(())
+    }
+
+    fn writer(&mut self) -> &mut BufWriter<File> {
+        // Safe unwrap, the writer is only taken once commit has succeeded
+        self.writer.as_mut().unwrap()
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer().flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+/// Creates a uniquely named hidden file in the same directory as [dest], so
+/// that the final rename never crosses a filesystem boundary.
+fn create_staging_file(dest: &Path) -> io::Result<(PathBuf, File)> {
+    let name = dest.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: not a file name", dest.display()),
+        )
+    })?;
+    let dir = match dest.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    for attempt in 0.. {
+        let tmp = dir.join(format!(
+            ".{}.catr-{}-{}",
+            name.to_string_lossy(),
+            process::id(),
+            attempt
+        ));
+        match OpenOptions::new().write(true).create_new(true).open(&tmp) {
+            Ok(file) => return Ok((tmp, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("ran out of staging file names")
+}
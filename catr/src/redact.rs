@@ -0,0 +1,162 @@
+use regex::{Captures, Regex};
+use std::fs;
+
+use crate::MyResult;
+
+/// Built-in detectors as (kind, pattern). When a pattern has a capture group
+/// only the first group is replaced, so the surrounding context stays
+/// readable.
+const BUILTIN: &[(&str, &str)] = &[
+    ("aws_access_key", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+    (
+        "aws_secret_key",
+        r#"(?i)aws_?secret_?access_?key["']?\s*[:=]\s*["']?([A-Za-z0-9/+=]{40})"#,
+    ),
+    ("bearer_token", r"(?i)\bbearer\s+([A-Za-z0-9\-._~+/]+=*)"),
+    ("email", r"[A-Za-z0-9._%+\-]+@[A-Za-z0-9.\-]+\.[A-Za-z]{2,}"),
+];
+
+const KEY_BEGIN: &str = r"-----BEGIN [A-Z0-9 ]*PRIVATE KEY-----";
+const KEY_END: &str = r"-----END [A-Z0-9 ]*PRIVATE KEY-----";
+
+#[derive(Debug)]
+struct Pattern {
+    kind: String,
+    regex: Regex,
+}
+
+/// Replaces secrets and PII in text with `[REDACTED:kind]` markers.
+#[derive(Debug)]
+pub struct Redactor {
+    patterns: Vec<Pattern>,
+    key_begin: Regex,
+    key_end: Regex,
+}
+
+impl Redactor {
+    /// Builds the redactor from the built-in detectors, plus the patterns in
+    /// [config_file] if one is given. The config file holds one `kind = regex`
+    /// pair per line; blank lines and lines starting with '#' are skipped.
+    pub fn new(config_file: Option<&str>) -> MyResult<Redactor> {
+        let mut patterns: Vec<Pattern> = BUILTIN
+            .iter()
+            .map(|(kind, pattern)| Pattern {
+                kind: kind.to_string(),
+                // Safe unwrap, the built-in patterns are known to be valid
+                regex: Regex::new(pattern).unwrap(),
+            })
+            .collect();
+
+        if let Some(filename) = config_file {
+            let content = fs::read_to_string(filename)
+                .map_err(|e| format!("Failed to open {}: {}", filename, e))?;
+            for (i, line) in content.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let (kind, pattern) = line
+                    .split_once('=')
+                    .ok_or_else(|| format!("{}:{}: expected 'kind = regex'", filename, i + 1))?;
+                let regex = Regex::new(pattern.trim())
+                    .map_err(|e| format!("{}:{}: {}", filename, i + 1, e))?;
+                patterns.push(Pattern {
+                    kind: kind.trim().to_string(),
+                    regex,
+                });
+            }
+        }
+
+        Ok(Redactor {
+            patterns,
+            // Safe unwraps, the key markers are known to be valid
+            key_begin: Regex::new(KEY_BEGIN).unwrap(),
+            key_end: Regex::new(KEY_END).unwrap(),
+        })
+    }
+
+    /// Starts redacting a new input. Private key blocks span several lines,
+    /// so each input needs its own state.
+    pub fn start(&self) -> LineRedactor<'_> {
+        LineRedactor {
+            redactor: self,
+            in_private_key: false,
+        }
+    }
+}
+
+/// Redacts one input line by line. Every line in yields exactly one line out,
+/// so line numbering is left intact.
+pub struct LineRedactor<'a> {
+    redactor: &'a Redactor,
+    in_private_key: bool,
+}
+
+impl LineRedactor<'_> {
+    pub fn redact(&mut self, line: &str) -> String {
+        let line = self.redact_private_key(line);
+        self.redactor
+            .patterns
+            .iter()
+            .fold(line, |line, pattern| redact_pattern(pattern, &line))
+    }
+
+    /// Blanks out everything between the private key markers, including the
+    /// markers themselves.
+    fn redact_private_key(&mut self, line: &str) -> String {
+        const MARKER: &str = "[REDACTED:private_key]";
+        let mut out = String::new();
+        let mut rest = line;
+        loop {
+            if self.in_private_key {
+                match self.redactor.key_end.find(rest) {
+                    Some(end) => {
+                        self.in_private_key = false;
+                        out.push_str(MARKER);
+                        rest = &rest[end.end()..];
+                    }
+                    None => {
+                        out.push_str(MARKER);
+                        return out;
+                    }
+                }
+            } else {
+                match self.redactor.key_begin.find(rest) {
+                    Some(begin) => {
+                        self.in_private_key = true;
+                        out.push_str(&rest[..begin.start()]);
+                        rest = &rest[begin.end()..];
+                    }
+                    None => {
+                        out.push_str(rest);
+                        return out;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn redact_pattern(pattern: &Pattern, line: &str) -> String {
+    let marker = format!("[REDACTED:{}]", pattern.kind);
+    pattern
+        .regex
+        .replace_all(line, |caps: &Captures| {
+            // Safe unwrap, group 0 is always the whole match
+            let whole = caps.get(0).unwrap();
+            match caps.get(1) {
+                Some(group) => {
+                    let start = group.start() - whole.start();
+                    let end = group.end() - whole.start();
+                    format!(
+                        "{}{}{}",
+                        &whole.as_str()[..start],
+                        marker,
+                        &whole.as_str()[end..]
+                    )
+                }
+                None => marker.clone(),
+            }
+        })
+        .into_owned()
+}
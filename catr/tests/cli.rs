@@ -11,6 +11,8 @@ const EMPTY: &str = "tests/inputs/empty.txt";
 const FOX: &str = "tests/inputs/fox.txt";
 const SPIDERS: &str = "tests/inputs/spiders.txt";
 const BUSTLE: &str = "tests/inputs/the-bustle.txt";
+const SECRETS: &str = "tests/inputs/secrets.txt";
+const REDACT_CONF: &str = "tests/inputs/redact.conf";
 
 /// Testing that the usage instruction shows up
 #[test]
@@ -195,3 +197,198 @@ fn all_n() -> TestResult {
 fn all_b() -> TestResult {
     run(&[FOX, SPIDERS, BUSTLE, "-b"], "tests/expected/all.b.out")
 }
+
+// --------------------------------------------------
+#[test]
+fn all_headers() -> TestResult {
+    run(
+        &["--headers", FOX, SPIDERS, BUSTLE],
+        "tests/expected/all.headers.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn all_separator() -> TestResult {
+    run(
+        &["--separator", "----", FOX, SPIDERS, BUSTLE],
+        "tests/expected/all.sep.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn headers_separator_n() -> TestResult {
+    run(
+        &["-n", "--headers", "--separator", "----", FOX, SPIDERS],
+        "tests/expected/fox_spiders.headers.sep.n.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn headers_skip_bad_file() -> TestResult {
+    let bad = gen_bad_file();
+    let expected = fs::read_to_string("tests/expected/fox.txt.out")?;
+    Command::cargo_bin(PRG)?
+        .args(["--headers", &bad, FOX])
+        .assert()
+        .success()
+        .stdout(format!("==> {} <==\n{}", FOX, expected));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn secrets_redact_n() -> TestResult {
+    run(
+        &["-n", "--redact", SECRETS],
+        "tests/expected/secrets.txt.redact.n.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn secrets_redact_config_n() -> TestResult {
+    run(
+        &["-n", "--redact", "--redact-config", REDACT_CONF, SECRETS],
+        "tests/expected/secrets.txt.redact_config.n.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_redact_pattern() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let conf = dir.path().join("bad.conf");
+    fs::write(&conf, "# comment\nbroken = (unclosed\n")?;
+    Command::cargo_bin(PRG)?
+        .args(["--redact", "--redact-config", conf.to_str().unwrap(), FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("bad.conf:2:"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn output_file() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let dest = dir.path().join("all.out");
+    let dest = dest.to_str().unwrap();
+    Command::cargo_bin(PRG)?
+        .args([FOX, SPIDERS, BUSTLE, "-o", dest])
+        .assert()
+        .success()
+        .stdout("");
+    assert_eq!(
+        fs::read_to_string(dest)?,
+        fs::read_to_string("tests/expected/all.out")?
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn output_file_append() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let dest = dir.path().join("append.out");
+    fs::write(&dest, "existing\n")?;
+    Command::cargo_bin(PRG)?
+        .args(["-n", FOX, "--append", "-o", dest.to_str().unwrap()])
+        .assert()
+        .success();
+    let expected = fs::read_to_string("tests/expected/fox.txt.n.out")?;
+    assert_eq!(
+        fs::read_to_string(&dest)?,
+        format!("existing\n{}", expected)
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn output_file_bad_input_keeps_dest() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let dest = dir.path().join("live.conf");
+    fs::write(&dest, "untouched\n")?;
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args([FOX, &bad, "-o", dest.to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(format!("Failed to open {}", bad)));
+    assert_eq!(fs::read_to_string(&dest)?, "untouched\n");
+    // The staging file should have been cleaned up
+    assert_eq!(fs::read_dir(dir.path())?.count(), 1);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn output_file_failed_rename_cleans_up() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    // A file cannot be renamed over a directory that has something in it
+    let dest = dir.path().join("taken");
+    fs::create_dir(&dest)?;
+    fs::write(dest.join("inside"), "")?;
+    Command::cargo_bin(PRG)?
+        .args([FOX, "-o", dest.to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to write"));
+    assert!(dest.join("inside").exists());
+    // The staging file should have been cleaned up
+    assert_eq!(fs::read_dir(dir.path())?.count(), 1);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[cfg(unix)]
+#[test]
+fn output_file_keeps_permissions() -> TestResult {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir()?;
+    let dest = dir.path().join("perms.out");
+    fs::write(&dest, "old\n")?;
+    fs::set_permissions(&dest, fs::Permissions::from_mode(0o640))?;
+    Command::cargo_bin(PRG)?
+        .args([FOX, "-o", dest.to_str().unwrap()])
+        .assert()
+        .success();
+    assert_eq!(fs::metadata(&dest)?.permissions().mode() & 0o777, 0o640);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[cfg(unix)]
+#[test]
+fn output_file_through_symlink() -> TestResult {
+    let dir = tempfile::tempdir()?;
+    let target = dir.path().join("live.conf");
+    let link = dir.path().join("link.conf");
+    fs::write(&target, "old\n")?;
+    std::os::unix::fs::symlink(&target, &link)?;
+    Command::cargo_bin(PRG)?
+        .args([FOX, "-o", link.to_str().unwrap()])
+        .assert()
+        .success();
+    assert!(fs::symlink_metadata(&link)?.file_type().is_symlink());
+    assert_eq!(
+        fs::read_to_string(&target)?,
+        fs::read_to_string("tests/expected/fox.txt.out")?
+    );
+    assert_eq!(fs::read_dir(dir.path())?.count(), 2);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_append_without_output() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--append", FOX])
+        .assert()
+        .failure();
+    Ok(())
+}
@@ -0,0 +1,149 @@
+//! Telling binary input apart from text, and a hex dump writer for showing
+//! binary input safely.
+
+use std::io::{self, Write};
+
+/// How much of the input is looked at to decide whether it is binary
+pub const SNIFF_SIZE: usize = 8 * 1024;
+
+/// Bytes shown on each line of a hex dump
+const DUMP_WIDTH: usize = 16;
+
+/// Guesses whether [block], the start of some input, is binary rather than
+/// text. Any NUL byte makes it binary, as does more than one byte in ten
+/// being outside valid UTF-8, which still lets through text in most legacy
+/// encodings.
+pub fn looks_binary(block: &[u8]) -> bool {
+    if block.contains(&0) {
+        return true;
+    }
+    let invalid: usize = block.utf8_chunks().map(|chunk| chunk.invalid().len()).sum();
+    invalid * 10 > block.len()
+}
+
+/// A writer that turns the bytes written to it into a hex dump in the style
+/// of `hexdump -C`, with the offset, sixteen bytes in hex and the printable
+/// ones as text. Call [HexDump::finish] to write the last, partial line.
+pub struct HexDump<W: Write> {
+    inner: W,
+    offset: u64,
+    line: Vec<u8>,
+    /// How many bytes are dumped at most
+    limit: u64,
+    /// How many bytes past [limit] were left out
+    dropped: u64,
+}
+
+impl<W: Write> HexDump<W> {
+    /// Dumps to [inner], starting from offset 0
+    pub fn new(inner: W) -> HexDump<W> {
+        HexDump::with_limit(inner, u64::MAX)
+    }
+
+    /// Dumps only the first [limit] bytes to [inner], and notes how many more
+    /// there were when finished
+    pub fn with_limit(inner: W, limit: u64) -> HexDump<W> {
+        HexDump {
+            inner,
+            offset: 0,
+            line: Vec::with_capacity(DUMP_WIDTH),
+            limit,
+            dropped: 0,
+        }
+    }
+
+    /// Writes out any partial line and gives back the wrapped writer
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.line.is_empty() {
+            self.write_line()?;
+        }
+        if self.dropped > 0 {
+            writeln!(self.inner, "... {} more bytes not shown", self.dropped)?;
+        }
+        Ok(self.inner)
+    }
+
+    fn write_line(&mut self) -> io::Result<()> {
+        write!(self.inner, "{:08x} ", self.offset)?;
+        for i in 0..DUMP_WIDTH {
+            if i % 8 == 0 {
+                write!(self.inner, " ")?;
+            }
+            match self.line.get(i) {
+                Some(byte) => write!(self.inner, "{:02x} ", byte)?,
+                None => write!(self.inner, "   ")?,
+            }
+        }
+        let text: String = self
+            .line
+            .iter()
+            .map(|&byte| match byte {
+                b' '..=b'~' => byte as char,
+                _ => '.',
+            })
+            .collect();
+        writeln!(self.inner, " |{}|", text)?;
+        self.offset += self.line.len() as u64;
+        self.line.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for HexDump<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            if self.offset + self.line.len() as u64 >= self.limit {
+                self.dropped += 1;
+                continue;
+            }
+            self.line.push(byte);
+            if self.line.len() == DUMP_WIDTH {
+                self.write_line()?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_or_text() {
+        assert!(!looks_binary(b""));
+        assert!(!looks_binary("Öne line, four words.\n".as_bytes()));
+        assert!(looks_binary(b"text with a \0 in it"));
+        // One stray Latin-1 byte is still text, a run of them is not
+        assert!(!looks_binary(b"caf\xe9 au lait, s'il vous pla\xeet"));
+        assert!(looks_binary(b"\x7f\xfe\xff\x80\x81 abc"));
+    }
+
+    #[test]
+    fn hex_dump() {
+        let mut dump = HexDump::new(Vec::new());
+        dump.write_all(b"Hello, world!\n\x00\x01\xffmore").unwrap();
+        let out = String::from_utf8(dump.finish().unwrap()).unwrap();
+        assert_eq!(
+            out,
+            "00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 01  |Hello, world!...|\n\
+             00000010  ff 6d 6f 72 65                                    |.more|\n"
+        );
+    }
+
+    #[test]
+    fn hex_dump_limit() {
+        let mut dump = HexDump::with_limit(Vec::new(), 4);
+        dump.write_all(b"abcdefgh").unwrap();
+        let out = String::from_utf8(dump.finish().unwrap()).unwrap();
+        assert_eq!(
+            out,
+            "00000000  61 62 63 64                                       |abcd|\n\
+             ... 4 more bytes not shown\n"
+        );
+    }
+}
@@ -0,0 +1,52 @@
+//! Reading from a file that may still be growing, for --follow.
+
+use std::{
+    io::{self, Read},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long to wait before looking for more input again
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A reader that treats the end of its input as "nothing yet" and waits for
+/// more to be written, until the optional deadline passes.
+pub struct Follow<R> {
+    inner: R,
+    deadline: Option<Instant>,
+}
+
+impl<R> Follow<R> {
+    /// Follows [inner], giving up [timeout] from now if one is given
+    pub fn new(inner: R, timeout: Option<Duration>) -> Follow<R> {
+        Follow {
+            inner,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+        }
+    }
+}
+
+impl<R: Read> Read for Follow<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.inner.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            let wait = match self.deadline {
+                None => POLL_INTERVAL,
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "timed out waiting for more input",
+                        ));
+                    }
+                    (deadline - now).min(POLL_INTERVAL)
+                }
+            };
+            thread::sleep(wait);
+        }
+    }
+}
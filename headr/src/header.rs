@@ -0,0 +1,87 @@
+//! The headers giving file names, and the --header-format templates that
+//! describe them.
+
+use std::fs;
+
+/// One part of a header template
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Name,
+    Path,
+    Size,
+    Index,
+}
+
+/// A parsed header template, such as the default `==> {name} <==`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderFormat {
+    pieces: Vec<Piece>,
+}
+
+impl HeaderFormat {
+    /// Parses [template], where `{name}`, `{path}`, `{size}` and `{index}`
+    /// are placeholders and `{{` or `}}` stand for a literal brace
+    pub fn parse(template: &str) -> Result<HeaderFormat, String> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| String::from("unclosed '{' in header format"))?;
+                    let piece = match &rest[..end] {
+                        "name" => Piece::Name,
+                        "path" => Piece::Path,
+                        "size" => Piece::Size,
+                        "index" => Piece::Index,
+                        other => return Err(format!("unknown placeholder '{{{}}}'", other)),
+                    };
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(piece);
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err(String::from("unmatched '}' in header format")),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(HeaderFormat { pieces })
+    }
+
+    /// Fills in the header for [filename], the [index]th file counting from 1.
+    /// Standard input has no path or size, so those are left empty for it.
+    pub fn render(&self, filename: &str, index: usize) -> String {
+        let on_disk = filename != "-";
+        self.pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Text(text) => text.clone(),
+                Piece::Name => filename.to_string(),
+                Piece::Path if on_disk => fs::canonicalize(filename)
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_else(|_| filename.to_string()),
+                Piece::Size if on_disk => fs::metadata(filename)
+                    .map(|meta| meta.len().to_string())
+                    .unwrap_or_default(),
+                Piece::Path | Piece::Size => String::new(),
+                Piece::Index => index.to_string(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,130 @@
+//! The reusable parts of headr: reader adapters that stop after the first
+//! lines or bytes of their input, plus the size parser, the CSV record
+//! splitter, the file prefetcher and the binary input check used by the
+//! command line program.
+//!
+//! ```
+//! use std::io::Read;
+//!
+//! let mut text = String::new();
+//! headr::head_lines(&b"one\ntwo\nthree\n"[..], 2).read_to_string(&mut text)?;
+//! assert_eq!(text, "one\ntwo\n");
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::io::{self, BufRead, Read, Take};
+
+pub mod binary;
+pub mod follow;
+pub mod prefetch;
+pub mod record;
+pub mod size;
+
+/// A reader over the first lines of another reader, made by [head_lines].
+/// Lines are kept as they are, delimiter included.
+#[derive(Debug)]
+pub struct HeadLines<R> {
+    inner: R,
+    remaining: u64,
+    delimiter: u8,
+}
+
+impl<R> HeadLines<R> {
+    /// Ends lines with [delimiter] instead of a newline
+    pub fn delimiter(mut self, delimiter: u8) -> HeadLines<R> {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Gives back the wrapped reader, positioned just after the last line
+    /// that was read
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: BufRead> Read for HeadLines<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 || out.is_empty() {
+            return Ok(0);
+        }
+        let available = self.inner.fill_buf()?;
+        let mut len = available.len().min(out.len());
+        for (i, byte) in available[..len].iter().enumerate() {
+            if *byte == self.delimiter {
+                self.remaining -= 1;
+                if self.remaining == 0 {
+                    len = i + 1;
+                    break;
+                }
+            }
+        }
+        out[..len].copy_from_slice(&available[..len]);
+        self.inner.consume(len);
+        Ok(len)
+    }
+}
+
+/// Wraps [reader] so that it ends after [num_lines] lines. The last line is
+/// still given in full when the input does not end with a newline.
+pub fn head_lines<R: BufRead>(reader: R, num_lines: u64) -> HeadLines<R> {
+    HeadLines {
+        inner: reader,
+        remaining: num_lines,
+        delimiter: b'\n',
+    }
+}
+
+/// Wraps [reader] so that it ends after [num_bytes] bytes
+pub fn head_bytes<R: Read>(reader: R, num_bytes: u64) -> Take<R> {
+    reader.take(num_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    fn read_all(mut reader: impl Read) -> Vec<u8> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn lines() {
+        let input = &b"one\ntwo\nthree\n"[..];
+        assert_eq!(read_all(head_lines(input, 0)), b"");
+        assert_eq!(read_all(head_lines(input, 2)), b"one\ntwo\n");
+        assert_eq!(read_all(head_lines(input, 5)), input);
+    }
+
+    #[test]
+    fn lines_across_small_buffers() {
+        let input = BufReader::with_capacity(2, &b"one\ntwo\nthree"[..]);
+        assert_eq!(read_all(head_lines(input, 3)), b"one\ntwo\nthree");
+    }
+
+    #[test]
+    fn lines_custom_delimiter() {
+        let input = &b"a\0b\nc\0d\0"[..];
+        assert_eq!(
+            read_all(head_lines(input, 2).delimiter(b'\0')),
+            b"a\0b\nc\0"
+        );
+    }
+
+    #[test]
+    fn lines_leave_the_rest() {
+        let mut head = head_lines(&b"one\ntwo\nthree\n"[..], 1);
+        assert_eq!(read_all(&mut head), b"one\n");
+        assert_eq!(read_all(head.into_inner()), b"two\nthree\n");
+    }
+
+    #[test]
+    fn bytes() {
+        let input = &b"one\ntwo\n"[..];
+        assert_eq!(read_all(head_bytes(input, 5)), b"one\nt");
+        assert_eq!(read_all(head_bytes(input, 100)), input);
+    }
+}
@@ -1,9 +1,44 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{bail, Result};
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
+use regex::bytes::Regex;
 use std::{
+    collections::VecDeque,
     convert::From,
-    io::{BufRead, BufReader, Read},
+    fs::File,
+    io::{self, BufRead, BufReader, Cursor, IsTerminal, Read, Seek, SeekFrom, Write},
+    time::Duration,
 };
+use unicode_segmentation::UnicodeSegmentation;
+
+mod header;
+
+use header::HeaderFormat;
+use headr::{
+    binary::{looks_binary, HexDump, SNIFF_SIZE},
+    follow::Follow,
+    head_bytes, head_lines,
+    prefetch::{for_each_ordered, prefetch_file, Prefetched},
+    record,
+    size::{parse_size, SizeError},
+};
+
+/// Size of the blocks used when scanning or buffering input
+const BLOCK_SIZE: usize = 8192;
+
+/// A count given to -n or -c. With a leading '-', the count is the number of
+/// lines or bytes to leave off the end instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Count {
+    First(u64),
+    AllButLast(u64),
+}
+
+fn parse_count(arg: &str) -> Result<Count, SizeError> {
+    match arg.strip_prefix('-') {
+        Some(num) => parse_size(num).map(Count::AllButLast),
+        None => parse_size(arg).map(Count::First),
+    }
+}
 
 #[derive(Debug, Parser)]
 #[command(name = "headr")]
@@ -20,14 +55,27 @@ pub struct Cli {
     file: Vec<String>,
 
     /// print the first NUM lines instead of the first 10; with the leading
-    /// '-', print all but the last NUM lines of each file. Overriden by -c
-    #[arg(short = 'n', long, value_name = "LINES", default_value = "10", value_parser=clap::value_parser!(u64).range(1..))]
-    lines: u64,
+    /// '-', print all but the last NUM lines of each file. Overriden by -c.
+    /// NUM may have a multiplier suffix such as K, MB or MiB
+    #[arg(short = 'n', long, value_name = "LINES", default_value = "10", value_parser = parse_count, allow_hyphen_values = true)]
+    lines: Count,
 
     /// Print the first K bytes of each file; with the leading '-', print
-    /// all but the last K bytes of each file
-    #[arg(short = 'c', long, value_name = "BYTES", conflicts_with = "lines", value_parser=clap::value_parser!(u64).range(1..))]
-    bytes: Option<u64>,
+    /// all but the last K bytes of each file. K may have a multiplier suffix:
+    /// b 512, kB 1000, K 1024, MB 1000*1000, M 1024*1024, and so on for G, T,
+    /// P, E, Z, Y, R, Q. Binary prefixes can be used too: KiB=K, MiB=M...
+    #[arg(short = 'c', long, value_name = "BYTES", conflicts_with = "lines", value_parser = parse_count, allow_hyphen_values = true)]
+    bytes: Option<Count>,
+
+    /// Print the first NUM characters of each file. Characters are Unicode
+    /// scalar values, so a multi-byte character is never cut in half
+    #[arg(short = 'C', long, value_name = "CHARS", value_parser = parse_size, conflicts_with_all = ["lines", "bytes"])]
+    chars: Option<u64>,
+
+    /// Print the first NUM user-perceived characters (extended grapheme
+    /// clusters) of each file
+    #[arg(long, value_name = "GRAPHEMES", value_parser = parse_size, conflicts_with_all = ["lines", "bytes", "chars"])]
+    graphemes: Option<u64>,
 
     /// Will never print the headers giving file names
     #[arg(short, long, visible_alias = "silent")]
@@ -36,87 +84,852 @@ pub struct Cli {
     /// Always print the headers giving file names
     #[arg(short, long)]
     verbose: bool,
+
+    /// Template for the headers giving file names. {name} is the name as
+    /// given, {path} the absolute path, {size} the size in bytes and {index}
+    /// the position of the file counting from 1
+    #[arg(long, value_name = "TEMPLATE", default_value = "==> {name} <==", value_parser = HeaderFormat::parse, allow_hyphen_values = true)]
+    header_format: HeaderFormat,
+
+    /// Print one JSON object per file, holding its name and the selected
+    /// lines, instead of the raw text
+    #[arg(long, conflicts_with_all = ["quiet", "verbose", "header_format"])]
+    json: bool,
+
+    /// Line delimiter is NUL, not newline
+    #[arg(short, long)]
+    zero_terminated: bool,
+
+    /// Print lines up to the first line matching REGEX, instead of a fixed
+    /// number of lines
+    #[arg(long, value_name = "REGEX", conflicts_with_all = ["lines", "bytes", "chars", "graphemes"])]
+    until: Option<Regex>,
+
+    /// Also print the line that matched --until
+    #[arg(long, requires = "until")]
+    inclusive: bool,
+
+    /// Print lines for as long as they match REGEX, instead of a fixed number
+    /// of lines
+    #[arg(long = "while", value_name = "REGEX", conflicts_with_all = ["lines", "bytes", "chars", "graphemes", "until"])]
+    while_match: Option<Regex>,
+
+    /// Use DELIM, a single byte, as the line delimiter
+    #[arg(long, value_name = "DELIM", conflicts_with = "zero_terminated", value_parser = parse_delimiter)]
+    delimiter: Option<u8>,
+
+    /// Treat the input as CSV, counting records instead of lines. A quoted
+    /// field may hold newlines without ending the record
+    #[arg(long, conflicts_with_all = ["bytes", "chars", "graphemes", "zero_terminated", "delimiter"])]
+    csv: bool,
+
+    /// Like --csv, but with fields separated by tabs
+    #[arg(long, conflicts_with_all = ["csv", "bytes", "chars", "graphemes", "zero_terminated", "delimiter"])]
+    tsv: bool,
+
+    /// Always print the first record as a header, on top of the records
+    /// selected by the other options
+    #[arg(long)]
+    keep_header: bool,
+
+    /// Drop the first NUM lines, or bytes with -c, before applying -n or -c.
+    /// Regular files are seeked past in bytes mode rather than read through
+    #[arg(long, value_name = "NUM", default_value = "0", value_parser = parse_size, conflicts_with_all = ["chars", "graphemes"])]
+    skip: u64,
+
+    /// Print lines A through B, counting from 1. Either end may be left
+    /// off, as in 'A:' or ':B'
+    #[arg(long, value_name = "A:B", value_parser = parse_line_range, conflicts_with_all = ["lines", "bytes", "chars", "graphemes", "skip", "until", "while_match"])]
+    lines_range: Option<LineRange>,
+
+    /// Keep waiting for a file to grow until the requested lines or bytes
+    /// have arrived, instead of stopping at its current end. Standard input
+    /// is read as usual. Counts with a leading '-' and ranges without an end
+    /// cannot be followed
+    #[arg(short, long)]
+    follow: bool,
+
+    /// With --follow, give up on a file after waiting for DURATION, such as
+    /// 30, 1.5s, 500ms or 2m. Giving up is reported as an error
+    #[arg(long, value_name = "DURATION", requires = "follow", value_parser = parse_duration)]
+    timeout: Option<Duration>,
+
+    /// Open and start reading up to N files at once when given several,
+    /// which helps on network filesystems. Output stays in argument order
+    #[arg(short, long, value_name = "N", default_value = "8", value_parser = clap::value_parser!(u64).range(1..))]
+    jobs: u64,
+
+    /// What to do with input that looks binary: 'notice' prints a note in
+    /// its place, 'hex' shows it as a hex dump, of at most 8K unless -c is
+    /// given, and 'raw' prints it as is.
+    /// The default, 'auto', is 'notice' on a terminal and 'raw' otherwise.
+    /// --json output is always complete: control bytes are escaped, but any
+    /// invalid UTF-8 is replaced with U+FFFD
+    #[arg(long, value_name = "MODE", value_enum, default_value_t = Binary::Auto)]
+    binary: Binary,
 }
 
-fn open(filename: &str) -> Result<Box<dyn BufRead>> {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Binary {
+    Auto,
+    Notice,
+    Hex,
+    Raw,
+}
+
+fn parse_duration(arg: &str) -> Result<Duration, String> {
+    let (num, scale) = if let Some(num) = arg.strip_suffix("ms") {
+        (num, 0.001)
+    } else if let Some(num) = arg.strip_suffix('s') {
+        (num, 1.0)
+    } else if let Some(num) = arg.strip_suffix('m') {
+        (num, 60.0)
+    } else {
+        (arg, 1.0)
+    };
+    num.parse::<f64>()
+        .ok()
+        .and_then(|num| Duration::try_from_secs_f64(num * scale).ok())
+        .ok_or_else(|| String::from("expected a duration such as 30, 1.5s, 500ms or 2m"))
+}
+
+/// The lines picked out by --lines-range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LineRange {
+    start: u64,
+    end: Option<u64>,
+}
+
+fn parse_line_range(arg: &str) -> Result<LineRange, String> {
+    let (start, end) = arg
+        .split_once(':')
+        .ok_or_else(|| String::from("expected a range such as 5:10"))?;
+    let start = match start {
+        "" => 1,
+        start => parse_size(start).map_err(|e| e.to_string())?,
+    };
+    let end = match end {
+        "" => None,
+        end => Some(parse_size(end).map_err(|e| e.to_string())?),
+    };
+    match (start, end) {
+        (0, _) => Err(String::from("lines are counted from 1")),
+        (start, Some(end)) if end < start => Err(String::from("the range ends before it starts")),
+        (start, end) => Ok(LineRange { start, end }),
+    }
+}
+
+/// How lines mode splits its input into the units it counts
+#[derive(Debug, Clone, Copy)]
+enum Splitter {
+    /// Lines ending with the given byte
+    Lines(u8),
+    /// CSV style records, with fields split by the given byte
+    Records(u8),
+}
+
+impl Splitter {
+    /// Reads the next unit from [reader] onto the end of [buf], returning the
+    /// number of bytes read
+    fn read_next(self, reader: &mut dyn BufRead, buf: &mut Vec<u8>) -> io::Result<usize> {
+        match self {
+            Splitter::Lines(delimiter) => reader.read_until(delimiter, buf),
+            Splitter::Records(separator) => record::read_record(separator, reader, buf),
+        }
+    }
+
+    /// The byte that ends each unit
+    fn terminator(self) -> u8 {
+        match self {
+            Splitter::Lines(delimiter) => delimiter,
+            Splitter::Records(_) => b'\n',
+        }
+    }
+}
+
+impl Cli {
+    /// Rejects the options that clap cannot tell apart by name alone. Counts
+    /// from the end of a file and ranges without an end both wait for an
+    /// end that a followed file never reaches.
+    fn check_follow(&self) -> Result<(), clap::Error> {
+        if !self.follow {
+            return Ok(());
+        }
+        let open_ended = matches!(self.lines_range, Some(LineRange { end: None, .. }));
+        let conflict = if self.counts_from_end() {
+            "a count with a leading '-'"
+        } else if open_ended {
+            "a --lines-range without an end"
+        } else {
+            return Ok(());
+        };
+        Err(Cli::command().error(
+            ErrorKind::ArgumentConflict,
+            format!("--follow cannot be used with {conflict}, as a followed file never ends"),
+        ))
+    }
+
+    /// Whether -n or -c were given a count with a leading '-'
+    fn counts_from_end(&self) -> bool {
+        matches!(self.lines, Count::AllButLast(_))
+            || matches!(self.bytes, Some(Count::AllButLast(_)))
+    }
+
+    /// Whether regular files are seeked around in rather than read through,
+    /// which is the case when leaving off the tail or skipping bytes
+    fn seeks(&self) -> bool {
+        self.counts_from_end() || (self.bytes.is_some() && self.skip_count() > 0)
+    }
+
+    /// The number of lines, or bytes in bytes mode, dropped from the start
+    fn skip_count(&self) -> u64 {
+        self.lines_range.map_or(self.skip, |range| range.start - 1)
+    }
+
+    /// The number of lines to print once the skipped ones are gone
+    fn line_count(&self) -> Count {
+        match self.lines_range {
+            Some(LineRange {
+                start,
+                end: Some(end),
+            }) => Count::First(end - start + 1),
+            Some(LineRange { end: None, .. }) => Count::First(u64::MAX),
+            None => self.lines,
+        }
+    }
+
+    /// How to split the input in lines mode
+    fn splitter(&self) -> Splitter {
+        match (self.csv, self.tsv, self.zero_terminated, self.delimiter) {
+            (true, _, _, _) => Splitter::Records(b','),
+            (_, true, _, _) => Splitter::Records(b'\t'),
+            (_, _, true, _) => Splitter::Lines(b'\0'),
+            (_, _, _, Some(delimiter)) => Splitter::Lines(delimiter),
+            _ => Splitter::Lines(b'\n'),
+        }
+    }
+}
+
+fn parse_delimiter(arg: &str) -> Result<u8, String> {
+    match arg.as_bytes() {
+        [byte] => Ok(*byte),
+        _ => Err(String::from("the delimiter must be a single byte")),
+    }
+}
+
+/// An opened input. Regular files are kept as they are so that the modes
+/// that need to know where the file ends can seek instead of buffering.
+enum Input {
+    Stream(Box<dyn BufRead>),
+    Regular(File),
+}
+
+impl Input {
+    fn into_reader(self) -> Box<dyn BufRead> {
+        match self {
+            Input::Stream(reader) => reader,
+            Input::Regular(file) => Box::new(BufReader::new(file)),
+        }
+    }
+
+    /// Looks at the start of the input, without using it up, to guess
+    /// whether it is binary
+    fn looks_binary(&mut self) -> io::Result<bool> {
+        match self {
+            Input::Stream(reader) => {
+                let block = reader.fill_buf()?;
+                Ok(looks_binary(&block[..block.len().min(SNIFF_SIZE)]))
+            }
+            Input::Regular(file) => {
+                let start = file.stream_position()?;
+                let mut block = Vec::with_capacity(SNIFF_SIZE);
+                file.take(SNIFF_SIZE as u64).read_to_end(&mut block)?;
+                file.seek(SeekFrom::Start(start))?;
+                Ok(looks_binary(&block))
+            }
+        }
+    }
+}
+
+fn open(filename: &str, config: &Cli) -> io::Result<Input> {
     match filename {
-        "-" => Ok(Box::new(BufReader::new(std::io::stdin()))),
-        _ => Ok(Box::new(BufReader::new(std::fs::File::open(filename)?))),
+        "-" => Ok(Input::Stream(Box::new(BufReader::new(std::io::stdin())))),
+        _ => {
+            let file = File::open(filename)?;
+            let meta = file.metadata()?;
+            // A followed file has no end yet, so it can only be streamed
+            if config.follow && meta.is_file() {
+                let follow = Follow::new(file, config.timeout);
+                return Ok(Input::Stream(Box::new(BufReader::new(follow))));
+            }
+            from_file(file, meta.is_file() && meta.len() > 0)
+        }
+    }
+}
+
+/// Files that report no size, like the ones under /proc, still have to be
+/// read through to find their end
+fn from_file(file: File, has_size: bool) -> io::Result<Input> {
+    if has_size {
+        Ok(Input::Regular(file))
+    } else {
+        Ok(Input::Stream(Box::new(BufReader::new(file))))
+    }
+}
+
+/// Turns a file read ahead by [prefetch_file] back into an [Input]
+fn from_prefetched(prefetched: Prefetched, config: &Cli) -> io::Result<Input> {
+    let Prefetched {
+        mut file,
+        head,
+        complete,
+    } = prefetched;
+    if complete {
+        return Ok(Input::Stream(Box::new(Cursor::new(head))));
+    }
+    // Only the modes that seek need the raw file back, the rest carry on
+    // from the block already read
+    if config.seeks() {
+        let meta = file.metadata()?;
+        file.seek(SeekFrom::Start(0))?;
+        return from_file(file, meta.is_file() && meta.len() > 0);
     }
+    let reader = Cursor::new(head).chain(file);
+    Ok(Input::Stream(Box::new(BufReader::new(reader))))
 }
 
-/// Prints out [num_lines] number of lines out of the [reader] file handle.
-fn read_lines(num_lines: u64, mut reader: Box<dyn BufRead>) -> Result<()> {
-    let mut buf = String::new();
+/// Prints out [num_lines] number of lines out of the [reader] file handle,
+/// split up by [splitter]. Lines are copied as raw bytes.
+fn read_lines(
+    num_lines: u64,
+    splitter: Splitter,
+    mut reader: impl BufRead,
+    out: &mut dyn Write,
+) -> Result<()> {
+    if let Splitter::Lines(delimiter) = splitter {
+        io::copy(&mut head_lines(reader, num_lines).delimiter(delimiter), out)?;
+        return Ok(());
+    }
+    let mut buf = Vec::new();
     for _ in 0..num_lines {
-        let n = reader.read_line(&mut buf)?;
+        let n = splitter.read_next(&mut reader, &mut buf)?;
         if n == 0 {
             break;
         } else {
-            print!("{}", buf);
+            out.write_all(&buf)?;
             buf.clear();
         }
     }
     Ok(())
 }
 
-/// Takes the number of bytes to read, and a file handle, and print those
-/// bytes out as a string.
-fn read_bytes(num_bytes: u64, reader: Box<dyn BufRead>) -> Result<()> {
-    let mut limit_read = reader.take(num_bytes);
-    let mut buf: Vec<u8> = vec![0; num_bytes as usize];
-    let bytes_read = limit_read.read(&mut buf)?;
-    let output = String::from_utf8_lossy(&buf[..bytes_read]);
-    print!("{}", output);
+/// What to do with a line in the pattern bounded modes
+enum Step {
+    Print,
+    PrintAndStop,
+    Stop,
+}
+
+/// Prints lines out of [reader] until [step] says to stop. [step] is given
+/// each line without its terminator.
+fn read_lines_bounded(
+    splitter: Splitter,
+    mut reader: Box<dyn BufRead>,
+    mut step: impl FnMut(&[u8]) -> Step,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let mut buf = Vec::new();
+    while splitter.read_next(&mut reader, &mut buf)? > 0 {
+        let line = buf.strip_suffix(&[splitter.terminator()]).unwrap_or(&buf);
+        match step(line) {
+            Step::Print => out.write_all(&buf)?,
+            Step::PrintAndStop => {
+                out.write_all(&buf)?;
+                break;
+            }
+            Step::Stop => break,
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+/// The units counted by the character modes
+#[derive(Debug, Clone, Copy)]
+enum TextUnit {
+    Char,
+    Grapheme,
+}
+
+/// Splits [bytes] into the units being counted, giving the length of each.
+/// Each broken UTF-8 sequence counts as a single unit and is kept as it is.
+fn unit_lens(bytes: &[u8], unit: TextUnit) -> Vec<usize> {
+    let mut lens = Vec::new();
+    for chunk in bytes.utf8_chunks() {
+        let valid = chunk.valid();
+        match unit {
+            TextUnit::Char => lens.extend(valid.chars().map(char::len_utf8)),
+            TextUnit::Grapheme => lens.extend(valid.graphemes(true).map(str::len)),
+        }
+        if !chunk.invalid().is_empty() {
+            lens.push(chunk.invalid().len());
+        }
+    }
+    lens
+}
+
+/// Prints the first [num_units] characters or grapheme clusters out of
+/// [reader]. Input is taken a block at a time. The last unit of a block may
+/// carry on into the next one, as a split UTF-8 sequence or a cluster that
+/// is not over yet, so it is held back until more has been read.
+fn read_text_units(
+    num_units: u64,
+    unit: TextUnit,
+    mut reader: Box<dyn BufRead>,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let mut remaining = num_units;
+    let mut pending = Vec::new();
+    while remaining > 0 {
+        let block = reader.fill_buf()?;
+        let at_end = block.is_empty();
+        let block_len = block.len().min(BLOCK_SIZE);
+        pending.extend_from_slice(&block[..block_len]);
+        reader.consume(block_len);
+
+        let mut lens = unit_lens(&pending, unit);
+        if at_end || lens.len() as u64 > remaining {
+            // Every unit wanted is complete
+            let len: usize = lens.iter().take(remaining as usize).sum();
+            out.write_all(&pending[..len])?;
+            break;
+        }
+        let held = lens.pop().unwrap_or(0);
+        let len = pending.len() - held;
+        out.write_all(&pending[..len])?;
+        pending.drain(..len);
+        remaining -= lens.len() as u64;
+    }
+    Ok(())
+}
+
+/// Copies the first [num_bytes] bytes of [reader] to [out] exactly as they
+/// are. The copy goes through a fixed size buffer and keeps reading until the
+/// count is reached or the input ends, so short reads from pipes are fine.
+fn read_bytes(num_bytes: u64, reader: Box<dyn BufRead>, out: &mut dyn Write) -> Result<()> {
+    io::copy(&mut head_bytes(reader, num_bytes), out)?;
+    Ok(())
+}
+
+/// Prints everything but the last [num_lines] lines of [reader]. Only the
+/// lines that may still turn out to be part of the tail are held in memory.
+fn elide_tail_lines(
+    num_lines: u64,
+    splitter: Splitter,
+    mut reader: Box<dyn BufRead>,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let mut held: VecDeque<Vec<u8>> = VecDeque::new();
+    let mut buf = Vec::new();
+    while splitter.read_next(&mut reader, &mut buf)? > 0 {
+        held.push_back(buf);
+        buf = if held.len() as u64 > num_lines {
+            // Safe unwrap, we just pushed a line
+            let mut line = held.pop_front().unwrap();
+            out.write_all(&line)?;
+            line.clear();
+            line
+        } else {
+            Vec::new()
+        };
+    }
+    Ok(())
+}
+
+/// Prints everything but the last [num_bytes] bytes of [reader], holding at
+/// most [num_bytes] plus one block in memory.
+fn elide_tail_bytes(
+    num_bytes: u64,
+    mut reader: Box<dyn BufRead>,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let mut held: VecDeque<u8> = VecDeque::new();
+    let mut buf = [0; BLOCK_SIZE];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        held.extend(&buf[..n]);
+        if held.len() as u64 > num_bytes {
+            // Fits in a usize, since it is less than the held length
+            let excess = held.len() - num_bytes as usize;
+            let (front, back) = held.as_slices();
+            if excess <= front.len() {
+                out.write_all(&front[..excess])?;
+            } else {
+                out.write_all(front)?;
+                out.write_all(&back[..excess - front.len()])?;
+            }
+            held.drain(..excess);
+        }
+    }
+    Ok(())
+}
+
+/// Finds the offset in [file] where its last [num_lines] lines start, by
+/// scanning backwards from the end for [delimiter]. A missing delimiter at
+/// the end of the file still counts as a line.
+fn tail_lines_offset(num_lines: u64, delimiter: u8, file: &mut File) -> Result<u64> {
+    let size = file.seek(SeekFrom::End(0))?;
+    if num_lines == 0 {
+        return Ok(size);
+    }
+    let mut remaining = num_lines;
+    let mut pos = size;
+    let mut buf = [0; BLOCK_SIZE];
+    while pos > 0 {
+        let len = pos.min(BLOCK_SIZE as u64) as usize;
+        pos -= len as u64;
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut buf[..len])?;
+        for (i, byte) in buf[..len].iter().enumerate().rev() {
+            let offset = pos + i as u64 + 1;
+            // The delimiter ending the file closes the last line, it does not
+            // start a new one
+            if *byte == delimiter && offset != size {
+                remaining -= 1;
+                if remaining == 0 {
+                    return Ok(offset);
+                }
+            }
+        }
+    }
+    Ok(0)
+}
+
+/// Prints the bytes of [file] from offset [start] up to offset [end]
+fn copy_range(start: u64, end: u64, mut file: File, out: &mut dyn Write) -> Result<()> {
+    file.seek(SeekFrom::Start(start))?;
+    io::copy(&mut file.take(end.saturating_sub(start)), out)?;
+    Ok(())
+}
+
+/// Drops the first [num_bytes] bytes of [input]. Regular files are seeked
+/// past instead of being read.
+fn skip_bytes(num_bytes: u64, input: Input) -> Result<Input> {
+    match input {
+        Input::Regular(mut file) => {
+            file.seek(SeekFrom::Start(num_bytes))?;
+            Ok(Input::Regular(file))
+        }
+        Input::Stream(mut reader) => {
+            io::copy(&mut (&mut reader).take(num_bytes), &mut io::sink())?;
+            Ok(Input::Stream(reader))
+        }
+    }
+}
+
+/// Drops the first [num_lines] lines of [reader], as split by [splitter]
+fn skip_lines(num_lines: u64, splitter: Splitter, reader: &mut dyn BufRead) -> Result<()> {
+    let mut buf = Vec::new();
+    for _ in 0..num_lines {
+        if splitter.read_next(reader, &mut buf)? == 0 {
+            break;
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+/// Runs [head_program] on [input], unless it looks binary and [binary] asks
+/// for it to be hidden or dumped in hex
+fn head_file(
+    config: &Cli,
+    binary: Binary,
+    filename: &str,
+    mut input: Input,
+    out: &mut dyn Write,
+) -> Result<()> {
+    if binary == Binary::Raw || !input.looks_binary()? {
+        return head_program(config, input, out);
+    }
+    if binary == Binary::Hex {
+        // Only -c asks for a number of bytes, whatever else selects could be
+        // the whole file, so the dump is kept to a preview
+        let mut dump = match config.bytes {
+            Some(_) => HexDump::new(out),
+            None => HexDump::with_limit(out, SNIFF_SIZE as u64),
+        };
+        // Whatever was read before a failure is still dumped
+        let result = head_program(config, input, &mut dump);
+        dump.finish()?;
+        return result;
+    }
+    writeln!(
+        out,
+        "headr: '{}' looks like a binary file, use --binary=hex or --binary=raw to show it",
+        filename
+    )?;
     Ok(())
 }
 
 /// Does the flow control logic on which functionality will be used on a single file
-fn head_program(config: &Cli, reader: Box<dyn BufRead>) -> Result<()> {
-    // We will be running the bytes mode if that flag has been touched, else
-    // run the lines mode
-    if let Some(num_bytes) = config.bytes {
-        read_bytes(num_bytes, reader)?;
+fn head_program(config: &Cli, input: Input, out: &mut dyn Write) -> Result<()> {
+    if let Some(num_chars) = config.chars {
+        return read_text_units(num_chars, TextUnit::Char, input.into_reader(), out);
+    }
+    if let Some(num_graphemes) = config.graphemes {
+        return read_text_units(num_graphemes, TextUnit::Grapheme, input.into_reader(), out);
+    }
+
+    let splitter = config.splitter();
+    let input = if config.keep_header {
+        let mut reader = input.into_reader();
+        read_lines(1, splitter, &mut reader, out)?;
+        Input::Stream(reader)
     } else {
-        read_lines(config.lines, reader)?;
+        input
+    };
+
+    // Skipping happens after the header, so the header is never skipped
+    let input = match (config.skip_count(), config.bytes) {
+        (0, _) => input,
+        (num_bytes, Some(_)) => skip_bytes(num_bytes, input)?,
+        (num_lines, None) => {
+            let mut reader = input.into_reader();
+            skip_lines(num_lines, splitter, &mut reader)?;
+            Input::Stream(reader)
+        }
+    };
+
+    // The pattern bounded modes decide where to stop line by line
+    if let Some(regex) = &config.until {
+        return read_lines_bounded(
+            splitter,
+            input.into_reader(),
+            |line| match (regex.is_match(line), config.inclusive) {
+                (false, _) => Step::Print,
+                (true, true) => Step::PrintAndStop,
+                (true, false) => Step::Stop,
+            },
+            out,
+        );
+    }
+    if let Some(regex) = &config.while_match {
+        return read_lines_bounded(
+            splitter,
+            input.into_reader(),
+            |line| {
+                if regex.is_match(line) {
+                    Step::Print
+                } else {
+                    Step::Stop
+                }
+            },
+            out,
+        );
+    }
+
+    // We will be running the bytes mode if that flag has been touched, else
+    // run the lines mode. Regular files know their size, so leaving off the
+    // tail can be done by seeking rather than buffering.
+    match (config.bytes, input) {
+        (Some(Count::First(num_bytes)), input) => read_bytes(num_bytes, input.into_reader(), out)?,
+        (Some(Count::AllButLast(num_bytes)), Input::Regular(mut file)) => {
+            let start = file.stream_position()?;
+            let size = file.metadata()?.len();
+            copy_range(start, size.saturating_sub(num_bytes), file, out)?;
+        }
+        (Some(Count::AllButLast(num_bytes)), input) => {
+            elide_tail_bytes(num_bytes, input.into_reader(), out)?
+        }
+        (None, input) => match (config.line_count(), splitter, input) {
+            (Count::First(num_lines), splitter, input) => {
+                read_lines(num_lines, splitter, input.into_reader(), out)?
+            }
+            (
+                Count::AllButLast(num_lines),
+                Splitter::Lines(delimiter),
+                Input::Regular(mut file),
+            ) => {
+                let start = file.stream_position()?;
+                let offset = tail_lines_offset(num_lines, delimiter, &mut file)?;
+                copy_range(start, offset, file, out)?;
+            }
+            (Count::AllButLast(num_lines), splitter, input) => {
+                elide_tail_lines(num_lines, splitter, input.into_reader(), out)?
+            }
+        },
     }
     Ok(())
 }
 
-fn run_file(config: &Cli, filename: &str) -> Result<()> {
-    match open(filename) {
-        Err(err) => eprintln!("{}: {}", filename, err),
-        Ok(reader) => head_program(config, reader)?,
+/// Standard output, noting whether writing to it failed, so that a failed
+/// write is not taken for a failed read of the input being copied
+struct Output<W: Write> {
+    inner: W,
+    failed: bool,
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.inner.write(buf);
+        self.note(&result);
+        result
     }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.inner.flush();
+        self.note(&result);
+        result
+    }
+}
+
+impl<W: Write> Output<W> {
+    fn note<T>(&mut self, result: &io::Result<T>) {
+        // An interrupted write is retried rather than given up on
+        if let Err(err) = result {
+            self.failed |= err.kind() != io::ErrorKind::Interrupted;
+        }
+    }
+}
+
+/// Describes an IO error the way the C library would, without the
+/// " (os error N)" that Rust appends to OS errors
+fn describe(err: &io::Error) -> String {
+    let msg = err.to_string();
+    match (err.raw_os_error(), msg.rfind(" (os error ")) {
+        (Some(_), Some(end)) => msg[..end].to_string(),
+        _ => msg,
+    }
+}
+
+/// Writes the output of head for [filename] as a JSON object on its own line,
+/// with [buf] split into lines at [terminator]
+fn write_json(filename: &str, buf: &[u8], terminator: u8, out: &mut dyn Write) -> Result<()> {
+    let lines: Vec<_> = if buf.is_empty() {
+        Vec::new()
+    } else {
+        buf.strip_suffix(&[terminator])
+            .unwrap_or(buf)
+            .split(|byte| *byte == terminator)
+            .map(String::from_utf8_lossy)
+            .collect()
+    };
+    let object = serde_json::json!({ "file": filename, "lines": lines });
+    writeln!(out, "{}", object)?;
     Ok(())
 }
 
-fn run(config: Cli) -> Result<()> {
-    let num_files = config.file.len() - 1;
-    let files = config.file.iter();
-    let header: bool = match num_files {
-        0 => config.verbose,
+/// Runs head over every file, carrying on past the ones that fail. Problems
+/// are reported on stderr, and the return value says whether every file was
+/// printed without any.
+fn run(config: Cli) -> Result<bool> {
+    let header: bool = match config.file.len() {
+        1 => config.verbose,
         _ => !config.quiet,
     };
-    if header {
-        for (i, filename) in files.enumerate() {
-            println!("==> {} <==", filename);
-            run_file(&config, filename)?;
-            if i < num_files {
-                println!();
+    let stdout = io::stdout();
+    let mut out = Output {
+        inner: stdout.lock(),
+        failed: false,
+    };
+    let mut all_ok = true;
+    let mut printed_any = false;
+    let binary = match config.binary {
+        Binary::Auto if out.inner.is_terminal() => Binary::Notice,
+        Binary::Auto => Binary::Raw,
+        binary => binary,
+    };
+    let mut handle = |index: usize, filename: &str, input: io::Result<Input>| -> Result<()> {
+        // Files that could not be opened get no header at all
+        let input = match input {
+            Ok(input) => input,
+            Err(err) => {
+                eprintln!(
+                    "headr: cannot open '{}' for reading: {}",
+                    filename,
+                    describe(&err)
+                );
+                all_ok = false;
+                return Ok(());
+            }
+        };
+        let result = if config.json {
+            // Whatever was read before a failure is still reported
+            let mut buf = Vec::new();
+            let result = head_program(&config, input, &mut buf);
+            write_json(filename, &buf, config.splitter().terminator(), &mut out).and(result)
+        } else {
+            let head = || {
+                if header {
+                    if printed_any {
+                        writeln!(out)?;
+                    }
+                    writeln!(out, "{}", config.header_format.render(filename, index + 1))?;
+                }
+                head_file(&config, binary, filename, input, &mut out)
+            };
+            head()
+        };
+        printed_any = true;
+        if let Err(err) = result {
+            let msg = err
+                .downcast_ref::<io::Error>()
+                .map_or_else(|| err.to_string(), describe);
+            // Every later file would fail to be written the same way
+            if out.failed {
+                bail!("headr: error writing 'standard output': {}", msg);
             }
+            eprintln!("headr: error reading '{}': {}", filename, msg);
+            all_ok = false;
         }
-    } else {
-        for filename in files {
-            run_file(&config, filename)?;
+        Ok(())
+    };
+
+    // Followed files are read as they grow, so there is nothing to read ahead
+    if config.jobs == 1 || config.file.len() == 1 || config.follow {
+        for (index, filename) in config.file.iter().enumerate() {
+            handle(index, filename, open(filename, &config))?;
         }
+    } else {
+        // Standard input is left to the main thread, and read in its turn
+        let jobs = config.jobs.min(config.file.len() as u64) as usize;
+        for_each_ordered(
+            &config.file,
+            jobs,
+            |filename| (filename != "-").then(|| prefetch_file(filename)),
+            |index, filename, prefetched| {
+                let input = match prefetched {
+                    None => open(filename, &config),
+                    Some(prefetched) => {
+                        prefetched.and_then(|prefetched| from_prefetched(prefetched, &config))
+                    }
+                };
+                handle(index, filename, input)
+            },
+        )?;
     }
-    Ok(())
+    if let Err(err) = out.flush() {
+        bail!("headr: error writing 'standard output': {}", describe(&err));
+    }
+    Ok(all_ok)
 }
 
 fn main() {
-    if let Err(err) = run(Cli::parse()) {
-        eprintln!("{}", err);
-        std::process::exit(1)
+    let cli = Cli::parse();
+    if let Err(err) = cli.check_follow() {
+        err.exit();
+    }
+    match run(cli) {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1)
+        }
     }
 }
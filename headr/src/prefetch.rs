@@ -0,0 +1,116 @@
+//! Reading the start of many files ahead of time on a few threads, for
+//! filesystems where every open and read has to wait on the network.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    sync::{mpsc, Mutex},
+    thread,
+};
+
+/// How much of each file is read ahead
+pub const PREFETCH_SIZE: u64 = 64 * 1024;
+
+/// A file opened on a worker thread, along with its first bytes
+pub struct Prefetched {
+    /// The file, positioned just after [head]
+    pub file: File,
+    /// The first bytes of the file
+    pub head: Vec<u8>,
+    /// Whether [head] holds the whole file
+    pub complete: bool,
+}
+
+/// Opens [filename] and reads the first [PREFETCH_SIZE] bytes of it. Only
+/// regular files are read ahead. A file that fails to read is rewound, so
+/// that reading it again can report the problem in order.
+pub fn prefetch_file(filename: &str) -> io::Result<Prefetched> {
+    let mut file = File::open(filename)?;
+    let mut head = Vec::new();
+    if !file.metadata()?.is_file() {
+        return Ok(Prefetched {
+            file,
+            head,
+            complete: false,
+        });
+    }
+    let complete = match (&mut file).take(PREFETCH_SIZE).read_to_end(&mut head) {
+        Ok(n) => (n as u64) < PREFETCH_SIZE,
+        Err(_) => {
+            head.clear();
+            file.seek(SeekFrom::Start(0))?;
+            false
+        }
+    };
+    Ok(Prefetched {
+        file,
+        head,
+        complete,
+    })
+}
+
+/// Runs [work] over [items] on up to [jobs] threads, and hands every result
+/// to [each] in the order of [items]. Workers are never more than twice
+/// [jobs] items ahead of [each], which keeps memory use bounded. The first
+/// error from [each] stops the run.
+pub fn for_each_ordered<T, U, E>(
+    items: &[T],
+    jobs: usize,
+    work: impl Fn(&T) -> U + Sync,
+    mut each: impl FnMut(usize, &T, U) -> Result<(), E>,
+) -> Result<(), E>
+where
+    T: Sync,
+    U: Send,
+{
+    let window = jobs * 2;
+    let (job_tx, job_rx) = mpsc::channel::<(usize, mpsc::Sender<U>)>();
+    let job_rx = Mutex::new(job_rx);
+
+    thread::scope(|scope| {
+        // Owned by this closure, so that returning early hangs up on the
+        // workers and lets the scope finish
+        let job_tx = job_tx;
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                // The lock is only held while waiting for a job, not while
+                // working on it. Safe unwrap, a worker only panics if [work]
+                // does.
+                let job = job_rx.lock().unwrap().recv();
+                let Ok((index, result_tx)) = job else {
+                    break;
+                };
+                let _ = result_tx.send(work(&items[index]));
+            });
+        }
+
+        let mut pending = VecDeque::new();
+        for index in 0..items.len().min(window) {
+            queue(&job_tx, &mut pending, index);
+        }
+        for (index, item) in items.iter().enumerate() {
+            if index + window < items.len() {
+                queue(&job_tx, &mut pending, index + window);
+            }
+            // Safe unwraps, one receiver is queued per item, and its worker
+            // always sends a result unless [work] panicked
+            let result = pending.pop_front().unwrap().recv().unwrap();
+            each(index, item, result)?;
+        }
+        Ok(())
+    })
+}
+
+/// Hands the item at [index] to the workers, keeping the receiver for its
+/// result at the back of [pending]
+fn queue<U>(
+    job_tx: &mpsc::Sender<(usize, mpsc::Sender<U>)>,
+    pending: &mut VecDeque<mpsc::Receiver<U>>,
+    index: usize,
+) {
+    let (result_tx, result_rx) = mpsc::channel();
+    // Safe unwrap, the workers outlive the sender
+    job_tx.send((index, result_tx)).unwrap();
+    pending.push_back(result_rx);
+}
@@ -0,0 +1,52 @@
+//! Splitting CSV and TSV input into records rather than physical lines, so
+//! that a quoted field holding newlines stays in one piece.
+
+use std::io::{self, BufRead};
+
+/// Where the scanner is within the current record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// At the start of a field, where a quote opens a quoted field
+    FieldStart,
+    /// Inside a field that is not quoted
+    Unquoted,
+    /// Inside a quoted field, where newlines and separators are data
+    Quoted,
+    /// Just after a quote in a quoted field. Another quote makes it an
+    /// escaped quote, anything else closes the field.
+    QuoteInQuoted,
+}
+
+/// Reads one record from [reader] onto the end of [buf], including the
+/// newline that ends it. Fields are split by [separator]. Returns the number
+/// of bytes read, which is 0 once the input has run out. A quoted field left
+/// open at the end of the input runs to the end of the input.
+pub fn read_record(
+    separator: u8,
+    reader: &mut dyn BufRead,
+    buf: &mut Vec<u8>,
+) -> io::Result<usize> {
+    let start = buf.len();
+    let mut state = State::FieldStart;
+    loop {
+        let line_start = buf.len();
+        if reader.read_until(b'\n', buf)? == 0 {
+            break;
+        }
+        for &byte in &buf[line_start..] {
+            state = match (state, byte) {
+                (State::Quoted, b'"') => State::QuoteInQuoted,
+                (State::Quoted, _) => State::Quoted,
+                (State::FieldStart | State::QuoteInQuoted, b'"') => State::Quoted,
+                (_, b'\n') => State::FieldStart,
+                (_, byte) if byte == separator => State::FieldStart,
+                _ => State::Unquoted,
+            };
+        }
+        // A newline inside a quoted field is part of the record
+        if state != State::Quoted {
+            break;
+        }
+    }
+    Ok(buf.len() - start)
+}
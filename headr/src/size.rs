@@ -0,0 +1,129 @@
+//! Parsing for sizes written the way GNU coreutils accepts them, such as
+//! `10K`, `1MiB`, `5MB` or `2b`.
+//!
+//! The suffixes understood are:
+//!
+//! | suffix               | multiplier         |
+//! |----------------------|--------------------|
+//! | `b`                  | 512                |
+//! | `K`, `k`, `KiB`      | 1024               |
+//! | `kB`, `KB`           | 1000               |
+//! | `M`, `m`, `MiB`      | 1024^2             |
+//! | `MB`                 | 1000^2             |
+//!
+//! and likewise for `G`, `T`, `P`, `E`, `Z`, `Y`, `R` and `Q`. Anything that
+//! does not fit in a `u64` is rejected rather than wrapped.
+
+use std::fmt;
+
+/// Why a size could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SizeError {
+    /// There were no leading digits
+    InvalidNumber,
+    /// The digits were followed by something that is not a known suffix
+    InvalidSuffix(String),
+    /// The size does not fit in a `u64`
+    TooLarge,
+}
+
+impl fmt::Display for SizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SizeError::InvalidNumber => write!(f, "invalid number"),
+            SizeError::InvalidSuffix(suffix) => write!(f, "invalid suffix '{}'", suffix),
+            SizeError::TooLarge => write!(f, "value too large"),
+        }
+    }
+}
+
+impl std::error::Error for SizeError {}
+
+/// Parses [arg] as a decimal number with an optional size suffix
+pub fn parse_size(arg: &str) -> Result<u64, SizeError> {
+    let digits_end = arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len());
+    let (digits, suffix) = arg.split_at(digits_end);
+    if digits.is_empty() {
+        return Err(SizeError::InvalidNumber);
+    }
+    let multiplier = multiplier(suffix)?;
+    digits
+        .parse::<u64>()
+        .map_err(|_| SizeError::TooLarge)?
+        .checked_mul(multiplier)
+        .ok_or(SizeError::TooLarge)
+}
+
+/// Looks up the multiplier for [suffix]
+fn multiplier(suffix: &str) -> Result<u64, SizeError> {
+    let invalid = || SizeError::InvalidSuffix(suffix.to_string());
+    let mut chars = suffix.chars();
+    let exponent = match chars.next() {
+        None => return Ok(1),
+        Some('b') if chars.as_str().is_empty() => return Ok(512),
+        Some('K' | 'k') => 1,
+        Some('M' | 'm') => 2,
+        Some('G') => 3,
+        Some('T') => 4,
+        Some('P') => 5,
+        Some('E') => 6,
+        Some('Z') => 7,
+        Some('Y') => 8,
+        Some('R') => 9,
+        Some('Q') => 10,
+        Some(_) => return Err(invalid()),
+    };
+    let base: u64 = match chars.as_str() {
+        "" | "iB" => 1024,
+        "B" => 1000,
+        _ => return Err(invalid()),
+    };
+    base.checked_pow(exponent).ok_or(SizeError::TooLarge)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_numbers() {
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("42"), Ok(42));
+        assert_eq!(parse_size("18446744073709551615"), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn suffixes() {
+        assert_eq!(parse_size("2b"), Ok(1024));
+        assert_eq!(parse_size("10K"), Ok(10 * 1024));
+        assert_eq!(parse_size("10k"), Ok(10 * 1024));
+        assert_eq!(parse_size("10KiB"), Ok(10 * 1024));
+        assert_eq!(parse_size("10kB"), Ok(10_000));
+        assert_eq!(parse_size("1MiB"), Ok(1 << 20));
+        assert_eq!(parse_size("1MB"), Ok(1_000_000));
+        assert_eq!(parse_size("3G"), Ok(3 << 30));
+        assert_eq!(parse_size("1E"), Ok(1 << 60));
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert_eq!(parse_size(""), Err(SizeError::InvalidNumber));
+        assert_eq!(parse_size("K"), Err(SizeError::InvalidNumber));
+        assert_eq!(parse_size("-1"), Err(SizeError::InvalidNumber));
+        assert_eq!(
+            parse_size("10Kb"),
+            Err(SizeError::InvalidSuffix("Kb".to_string()))
+        );
+        assert_eq!(
+            parse_size("2bB"),
+            Err(SizeError::InvalidSuffix("bB".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_overflow() {
+        assert_eq!(parse_size("16E"), Err(SizeError::TooLarge));
+        assert_eq!(parse_size("1Z"), Err(SizeError::TooLarge));
+        assert_eq!(parse_size("18446744073709551616"), Err(SizeError::TooLarge));
+    }
+}
@@ -11,6 +11,7 @@ const ONE: &str = "./tests/inputs/one.txt";
 const TWO: &str = "./tests/inputs/two.txt";
 const THREE: &str = "./tests/inputs/three.txt";
 const TWELVE: &str = "./tests/inputs/twelve.txt";
+const BINARY: &str = "./tests/inputs/binary.bin";
 
 fn random_string() -> String {
     rand::thread_rng()
@@ -32,10 +33,7 @@ fn gen_bad_file() -> String {
 #[test]
 fn dies_bad_bytes() -> Result<()> {
     let bad = random_string();
-    let expected = format!(
-        "invalid value '{}' for '--bytes <BYTES>': invalid digit found in string",
-        bad
-    );
+    let expected = format!("invalid value '{}' for '--bytes <BYTES>': invalid", bad);
 
     Command::cargo_bin(PRG)?
         .args(["-c", &bad, EMPTY])
@@ -50,7 +48,7 @@ fn dies_bad_bytes() -> Result<()> {
 fn dies_bad_lines() -> Result<()> {
     let bad = random_string();
     let expected = format!(
-        "error: invalid value '{}' for '--lines <LINES>': invalid digit found in string",
+        "error: invalid value '{}' for '--lines <LINES>': invalid",
         bad
     );
     Command::cargo_bin(PRG)?
@@ -62,6 +60,32 @@ fn dies_bad_lines() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn dies_bad_suffix() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-c", "10Kb", EMPTY])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "invalid value '10Kb' for '--bytes <BYTES>': invalid suffix 'Kb'",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn dies_too_large() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-n", "-16E", EMPTY])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "invalid value '-16E' for '--lines <LINES>': value too large",
+        ));
+
+    Ok(())
+}
+
 #[test]
 fn dies_bytes_and_lines() -> Result<()> {
     let msg = "the argument '--lines <LINES>' cannot be \
@@ -79,11 +103,59 @@ fn dies_bytes_and_lines() -> Result<()> {
 #[test]
 fn skips_bad_file() -> Result<()> {
     let bad = gen_bad_file();
-    let expected = format!("{bad}: .* [(]os error 2[)]");
+    let expected = format!("headr: cannot open '{bad}' for reading: No such file or directory\n");
+    let stdout = format!(
+        "==> {EMPTY} <==\n\n==> {ONE} <==\n{}",
+        fs::read_to_string(ONE)?
+    );
     Command::cargo_bin(PRG)?
         .args([EMPTY, &bad, ONE])
         .assert()
-        .stderr(predicate::str::is_match(expected)?);
+        .code(1)
+        .stdout(stdout)
+        .stderr(expected);
+
+    Ok(())
+}
+
+#[test]
+fn bad_file_last_no_header() -> Result<()> {
+    let bad = gen_bad_file();
+    let stdout = format!("==> {ONE} <==\n{}", fs::read_to_string(ONE)?);
+    Command::cargo_bin(PRG)?
+        .args([ONE, &bad])
+        .assert()
+        .code(1)
+        .stdout(stdout);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn reports_directory() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests", ONE])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("==> tests <==\n\n==> "))
+        .stderr("headr: error reading 'tests': Is a directory\n");
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn stops_on_write_error() -> Result<()> {
+    let output = std::process::Command::new(assert_cmd::cargo::cargo_bin(PRG))
+        .args(["-q", ONE, TWO, THREE])
+        .stdout(File::create("/dev/full")?)
+        .output()?;
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        "headr: error writing 'standard output': No space left on device\n"
+    );
 
     Ok(())
 }
@@ -367,6 +439,123 @@ fn twelve_c4_stdin() -> Result<()> {
     run_stdin(&["-c", "4"], TWELVE, "tests/expected/twelve.txt.c4.out")
 }
 
+// --------------------------------------------------
+#[test]
+fn empty_n_minus2() -> Result<()> {
+    run(&[EMPTY, "-n", "-2"], "tests/expected/empty.txt.n-2.out")
+}
+
+#[test]
+fn empty_c_minus4() -> Result<()> {
+    run(&[EMPTY, "-c", "-4"], "tests/expected/empty.txt.c-4.out")
+}
+
+#[test]
+fn empty_n_minus2_stdin() -> Result<()> {
+    run_stdin(&["-n", "-2"], EMPTY, "tests/expected/empty.txt.n-2.out")
+}
+
+#[test]
+fn empty_c_minus4_stdin() -> Result<()> {
+    run_stdin(&["-c", "-4"], EMPTY, "tests/expected/empty.txt.c-4.out")
+}
+
+#[test]
+fn one_n_minus2() -> Result<()> {
+    run(&[ONE, "-n", "-2"], "tests/expected/one.txt.n-2.out")
+}
+
+#[test]
+fn one_c_minus4() -> Result<()> {
+    run(&[ONE, "-c", "-4"], "tests/expected/one.txt.c-4.out")
+}
+
+#[test]
+fn one_n_minus2_stdin() -> Result<()> {
+    run_stdin(&["-n", "-2"], ONE, "tests/expected/one.txt.n-2.out")
+}
+
+#[test]
+fn one_c_minus4_stdin() -> Result<()> {
+    run_stdin(&["-c", "-4"], ONE, "tests/expected/one.txt.c-4.out")
+}
+
+#[test]
+fn two_n_minus2() -> Result<()> {
+    run(&[TWO, "-n", "-2"], "tests/expected/two.txt.n-2.out")
+}
+
+#[test]
+fn two_c_minus4() -> Result<()> {
+    run(&[TWO, "-c", "-4"], "tests/expected/two.txt.c-4.out")
+}
+
+#[test]
+fn two_n_minus2_stdin() -> Result<()> {
+    run_stdin(&["-n", "-2"], TWO, "tests/expected/two.txt.n-2.out")
+}
+
+#[test]
+fn two_c_minus4_stdin() -> Result<()> {
+    run_stdin(&["-c", "-4"], TWO, "tests/expected/two.txt.c-4.out")
+}
+
+#[test]
+fn three_n_minus2() -> Result<()> {
+    run(&[THREE, "-n", "-2"], "tests/expected/three.txt.n-2.out")
+}
+
+#[test]
+fn three_c_minus4() -> Result<()> {
+    run(&[THREE, "-c", "-4"], "tests/expected/three.txt.c-4.out")
+}
+
+#[test]
+fn three_n_minus2_stdin() -> Result<()> {
+    run_stdin(&["-n", "-2"], THREE, "tests/expected/three.txt.n-2.out")
+}
+
+#[test]
+fn three_c_minus4_stdin() -> Result<()> {
+    run_stdin(&["-c", "-4"], THREE, "tests/expected/three.txt.c-4.out")
+}
+
+#[test]
+fn twelve_n_minus2() -> Result<()> {
+    run(&[TWELVE, "-n", "-2"], "tests/expected/twelve.txt.n-2.out")
+}
+
+#[test]
+fn twelve_c_minus4() -> Result<()> {
+    run(&[TWELVE, "-c", "-4"], "tests/expected/twelve.txt.c-4.out")
+}
+
+#[test]
+fn twelve_n_minus2_stdin() -> Result<()> {
+    run_stdin(&["-n", "-2"], TWELVE, "tests/expected/twelve.txt.n-2.out")
+}
+
+#[test]
+fn twelve_c_minus4_stdin() -> Result<()> {
+    run_stdin(&["-c", "-4"], TWELVE, "tests/expected/twelve.txt.c-4.out")
+}
+
+#[test]
+fn n_minus_keeps_unterminated_last_line() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-n", "-1"])
+        .write_stdin("a\nb\nc")
+        .assert()
+        .success()
+        .stdout("a\nb\n");
+    Ok(())
+}
+
+#[test]
+fn n_minus_more_than_file() -> Result<()> {
+    run(&[TWELVE, "-n", "-100"], "tests/expected/empty.txt.out")
+}
+
 // --------------------------------------------------
 #[test]
 fn multiple_files() -> Result<()> {
@@ -412,3 +601,842 @@ fn multiple_files_c4() -> Result<()> {
         "tests/expected/all.c4.out",
     )
 }
+
+#[test]
+fn multiple_files_n_minus2() -> Result<()> {
+    run(
+        &[EMPTY, ONE, TWO, THREE, TWELVE, "-n", "-2"],
+        "tests/expected/all.n-2.out",
+    )
+}
+
+#[test]
+fn multiple_files_c_minus4() -> Result<()> {
+    run(
+        &["-c", "-4", EMPTY, ONE, TWO, THREE, TWELVE],
+        "tests/expected/all.c-4.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn bytes_are_not_lossy() -> Result<()> {
+    // The first byte of one.txt is half of a two byte character
+    let expected = fs::read("tests/expected/one.txt.c1.out")?;
+    Command::cargo_bin(PRG)?
+        .args(["-c", "1", ONE])
+        .assert()
+        .success()
+        .stdout(expected);
+
+    Ok(())
+}
+
+#[test]
+fn bytes_huge_count() -> Result<()> {
+    let expected = fs::read(TWELVE)?;
+    Command::cargo_bin(PRG)?
+        .args(["-c", "10G", TWELVE])
+        .assert()
+        .success()
+        .stdout(expected);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn bytes_from_chunked_pipe() -> Result<()> {
+    // Feed stdin through a shell pipeline so it arrives in several writes
+    let output = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!(
+            "(printf abc; sleep 0.2; printf def) | {} -c 5",
+            assert_cmd::cargo::cargo_bin(PRG).display()
+        ))
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"abcde");
+
+    Ok(())
+}
+
+// --------------------------------------------------
+fn run_suffix(args: &[&str], expected_len: usize) -> Result<()> {
+    let input = "0123456789abcde\n".repeat(1000);
+    Command::cargo_bin(PRG)?
+        .args(args)
+        .write_stdin(input.as_str())
+        .assert()
+        .success()
+        .stdout(input[..expected_len].to_string());
+
+    Ok(())
+}
+
+#[test]
+fn bytes_suffix_k() -> Result<()> {
+    run_suffix(&["-c", "10K"], 10 * 1024)
+}
+
+#[test]
+fn bytes_suffix_kib() -> Result<()> {
+    run_suffix(&["-c", "1KiB"], 1024)
+}
+
+#[test]
+fn bytes_suffix_kb() -> Result<()> {
+    run_suffix(&["-c", "1kB"], 1000)
+}
+
+#[test]
+fn bytes_suffix_blocks() -> Result<()> {
+    run_suffix(&["-c", "2b"], 1024)
+}
+
+#[test]
+fn bytes_suffix_negative() -> Result<()> {
+    run_suffix(&["-c", "-15K"], 16000 - 15 * 1024)
+}
+
+#[test]
+fn lines_suffix_k() -> Result<()> {
+    run_suffix(&["-n", "-1k"], 0)
+}
+
+// --------------------------------------------------
+fn run_delimited(args: &[&str], input: &str, expected: &str) -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(args)
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(expected.to_string());
+
+    Ok(())
+}
+
+#[test]
+fn zero_terminated() -> Result<()> {
+    run_delimited(&["-z", "-n", "2"], "a\0b\nc\0d\0", "a\0b\nc\0")
+}
+
+#[test]
+fn zero_terminated_long() -> Result<()> {
+    run_delimited(&["--zero-terminated", "-n", "1"], "a\nb\0c\0", "a\nb\0")
+}
+
+#[test]
+fn zero_terminated_all_but_last() -> Result<()> {
+    run_delimited(&["-z", "-n", "-1"], "a\0b\0c", "a\0b\0")
+}
+
+#[test]
+fn zero_terminated_all_but_last_file() -> Result<()> {
+    run(&["-z", "-n", "-1", TWELVE], "tests/expected/empty.txt.out")
+}
+
+#[test]
+fn custom_delimiter() -> Result<()> {
+    run_delimited(&["--delimiter", ",", "-n", "3"], "a,b\n,c,d,e", "a,b\n,c,")
+}
+
+#[test]
+fn dies_bad_delimiter() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--delimiter", "ab", EMPTY])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "the delimiter must be a single byte",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn dies_zero_terminated_and_delimiter() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-z", "--delimiter", ",", EMPTY])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn until_excludes_match() -> Result<()> {
+    run_delimited(
+        &["--until", "^$"],
+        "Date: x\nFrom: y\n\nbody\n",
+        "Date: x\nFrom: y\n",
+    )
+}
+
+#[test]
+fn until_inclusive() -> Result<()> {
+    run_delimited(
+        &["--until", "^end", "--inclusive"],
+        "a\nend here\nb\n",
+        "a\nend here\n",
+    )
+}
+
+#[test]
+fn until_no_match_prints_all() -> Result<()> {
+    run(&[TWELVE, "--until", "nothing"], TWELVE)
+}
+
+#[test]
+fn while_matching() -> Result<()> {
+    run_delimited(
+        &["--while", "^#"],
+        "# one\n# two\ncode\n# three\n",
+        "# one\n# two\n",
+    )
+}
+
+#[test]
+fn while_zero_terminated() -> Result<()> {
+    run_delimited(&["-z", "--while", "^a"], "ab\0ac\0b\0ad\0", "ab\0ac\0")
+}
+
+#[test]
+fn until_multiple_files() -> Result<()> {
+    let expected = format!(
+        "==> {TWELVE} <==\none\ntwo\nthree\nfour\n\n==> {TWO} <==\nTwo lines.\nFour words.\n"
+    );
+    Command::cargo_bin(PRG)?
+        .args(["--until", "fi", TWELVE, TWO])
+        .assert()
+        .success()
+        .stdout(expected);
+
+    Ok(())
+}
+
+#[test]
+fn dies_bad_regex() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--until", "(", EMPTY])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "invalid value '(' for '--until <REGEX>'",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn dies_until_and_lines() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--until", "x", "-n", "3", EMPTY])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn chars_keep_whole_characters() -> Result<()> {
+    run_delimited(&["-C", "1"], "Öne line", "Ö")
+}
+
+#[test]
+fn chars_across_lines() -> Result<()> {
+    run_delimited(&["--chars", "7"], "héllo\nwörld\n", "héllo\nw")
+}
+
+#[test]
+fn chars_file() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-C", "3", ONE])
+        .assert()
+        .success()
+        .stdout("Öne");
+
+    Ok(())
+}
+
+#[test]
+fn chars_invalid_utf8() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-C", "3"])
+        .write_stdin(&b"a\xff\xfeb"[..])
+        .assert()
+        .success()
+        .stdout(&b"a\xff\xfe"[..]);
+
+    Ok(())
+}
+
+#[test]
+fn graphemes_keep_clusters() -> Result<()> {
+    // An e with a combining accent, then a thumbs up with a skin tone
+    run_delimited(
+        &["--graphemes", "2"],
+        "e\u{301}\u{1f44d}\u{1f3fd}abc",
+        "e\u{301}\u{1f44d}\u{1f3fd}",
+    )
+}
+
+#[test]
+fn graphemes_crlf_is_one() -> Result<()> {
+    run_delimited(&["--graphemes", "3"], "a\r\nbc", "a\r\nb")
+}
+
+#[test]
+fn chars_across_blocks() -> Result<()> {
+    // The accents straddle the end of the first 8K block
+    let start = "a".repeat(8191);
+    run_delimited(
+        &["-C", "8193"],
+        &format!("{start}éxyz"),
+        &format!("{start}éx"),
+    )?;
+    run_delimited(
+        &["--graphemes", "8193"],
+        &format!("{start}e\u{301}bc"),
+        &format!("{start}e\u{301}b"),
+    )
+}
+
+#[test]
+fn chars_without_newlines_stop_early() -> Result<()> {
+    // Input that never ends a line, and never ends at all while headr runs
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin(PRG))
+        .args(["-C", "5"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all("é".repeat(10_000).as_bytes())?;
+    let mut stdout = String::new();
+    child.stdout.take().unwrap().read_to_string(&mut stdout)?;
+    assert_eq!(stdout, "ééééé");
+    assert!(child.wait()?.success());
+    drop(stdin);
+
+    Ok(())
+}
+
+#[test]
+fn dies_chars_and_bytes() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-C", "1", "-c", "1", EMPTY])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+
+    Ok(())
+}
+
+// --------------------------------------------------
+const CSV: &str = "id,name,note\n1,a,\"multi\nline\"\n2,b,\"say \"\"hi\"\"\nthere\"\n3,c,plain\n";
+
+#[test]
+fn csv_counts_records() -> Result<()> {
+    run_delimited(
+        &["--csv", "-n", "2"],
+        CSV,
+        "id,name,note\n1,a,\"multi\nline\"\n",
+    )
+}
+
+#[test]
+fn csv_escaped_quotes() -> Result<()> {
+    run_delimited(
+        &["--csv", "-n", "3"],
+        CSV,
+        "id,name,note\n1,a,\"multi\nline\"\n2,b,\"say \"\"hi\"\"\nthere\"\n",
+    )
+}
+
+#[test]
+fn csv_keep_header() -> Result<()> {
+    run_delimited(
+        &["--csv", "--keep-header", "-n", "1"],
+        CSV,
+        "id,name,note\n1,a,\"multi\nline\"\n",
+    )
+}
+
+#[test]
+fn csv_keep_header_all_but_last() -> Result<()> {
+    run_delimited(
+        &["--csv", "--keep-header", "-n", "-2"],
+        CSV,
+        "id,name,note\n1,a,\"multi\nline\"\n",
+    )
+}
+
+#[test]
+fn csv_quote_mid_field_is_data() -> Result<()> {
+    run_delimited(
+        &["--csv", "-n", "1"],
+        "a 5\" screen,b\nc,d\n",
+        "a 5\" screen,b\n",
+    )
+}
+
+#[test]
+fn tsv_counts_records() -> Result<()> {
+    run_delimited(
+        &["--tsv", "-n", "2"],
+        "id\tnote\n1\t\"tab\there\nand newline\"\n2\tx\n",
+        "id\tnote\n1\t\"tab\there\nand newline\"\n",
+    )
+}
+
+#[test]
+fn dies_csv_and_bytes() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--csv", "-c", "1", EMPTY])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+
+    Ok(())
+}
+
+// --------------------------------------------------
+fn run_output(args: &[&str], expected: &str) -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(args)
+        .assert()
+        .success()
+        .stdout(expected.to_string());
+
+    Ok(())
+}
+
+#[test]
+fn skip_lines() -> Result<()> {
+    run_output(&["--skip", "2", "-n", "3", TWELVE], "three\nfour\nfive\n")
+}
+
+#[test]
+fn skip_lines_all_but_last() -> Result<()> {
+    run_output(&["--skip", "8", "-n", "-2", TWELVE], "nine\nten\n")
+}
+
+#[test]
+fn skip_bytes_file() -> Result<()> {
+    run_output(&["--skip", "2", "-c", "4", TWELVE], "e\ntw")
+}
+
+#[test]
+fn skip_bytes_stdin() -> Result<()> {
+    run_delimited(&["--skip", "2", "-c", "4"], "one\ntwo\n", "e\ntw")
+}
+
+#[test]
+fn skip_bytes_all_but_last() -> Result<()> {
+    run_output(&["--skip", "60", "-c", "-1", TWELVE], "ve")
+}
+
+#[test]
+fn skip_past_end() -> Result<()> {
+    run_output(&["--skip", "1K", "-c", "-1", TWELVE], "")
+}
+
+#[test]
+fn skip_keeps_csv_header() -> Result<()> {
+    run_delimited(
+        &["--csv", "--keep-header", "--skip", "1", "-n", "1"],
+        CSV,
+        "id,name,note\n2,b,\"say \"\"hi\"\"\nthere\"\n",
+    )
+}
+
+#[test]
+fn lines_range() -> Result<()> {
+    run_output(&["--lines-range", "3:5", TWELVE], "three\nfour\nfive\n")
+}
+
+#[test]
+fn lines_range_open_end() -> Result<()> {
+    run_output(&["--lines-range", "11:", TWELVE], "eleven\ntwelve\n")
+}
+
+#[test]
+fn lines_range_open_start() -> Result<()> {
+    run_output(&["--lines-range", ":2", TWELVE], "one\ntwo\n")
+}
+
+#[test]
+fn dies_backwards_lines_range() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--lines-range", "5:3", TWELVE])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("the range ends before it starts"));
+
+    Ok(())
+}
+
+#[test]
+fn dies_zero_lines_range() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--lines-range", "0:3", TWELVE])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("lines are counted from 1"));
+
+    Ok(())
+}
+
+// --------------------------------------------------
+/// Starts headr on a log file that gets [later] appended to it a moment
+/// after headr has started waiting.
+fn run_follow(args: &[&str], initial: &str, later: &str) -> Result<std::process::Output> {
+    let dir = tempfile::tempdir()?;
+    let log = dir.path().join("server.log");
+    fs::write(&log, initial)?;
+    let child = std::process::Command::new(assert_cmd::cargo::cargo_bin(PRG))
+        .args(args)
+        .arg(&log)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    fs::OpenOptions::new()
+        .append(true)
+        .open(&log)?
+        .write_all(later.as_bytes())?;
+
+    Ok(child.wait_with_output()?)
+}
+
+#[test]
+fn follow_waits_for_lines() -> Result<()> {
+    let output = run_follow(
+        &["-n", "3", "--follow", "--timeout", "10"],
+        "one\n",
+        "two\nthree\nfour\n",
+    )?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?, "one\ntwo\nthree\n");
+
+    Ok(())
+}
+
+#[test]
+fn follow_until_match() -> Result<()> {
+    let output = run_follow(
+        &[
+            "--until",
+            "server started",
+            "--inclusive",
+            "-f",
+            "--timeout",
+            "10s",
+        ],
+        "",
+        "booting\nserver started on :8080\nrequest\n",
+    )?;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "booting\nserver started on :8080\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn follow_times_out() -> Result<()> {
+    let output = run_follow(&["-n", "5", "-f", "--timeout", "500ms"], "one\n", "two\n")?;
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout)?, "one\ntwo\n");
+    assert!(String::from_utf8(output.stderr)?.contains("timed out waiting for more input"));
+
+    Ok(())
+}
+
+/// Runs headr with --follow and [args] on a file, expecting it to refuse
+/// straight away rather than wait for an end that never comes
+fn dies_follow_without_end(args: &[&str]) -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .arg("-f")
+        .args(args)
+        .arg(TWELVE)
+        .timeout(std::time::Duration::from_secs(5))
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("a followed file never ends"));
+
+    Ok(())
+}
+
+#[test]
+fn dies_follow_all_but_last_lines() -> Result<()> {
+    dies_follow_without_end(&["-n", "-1"])
+}
+
+#[test]
+fn dies_follow_all_but_last_bytes() -> Result<()> {
+    dies_follow_without_end(&["-c", "-5"])
+}
+
+#[test]
+fn dies_follow_open_lines_range() -> Result<()> {
+    dies_follow_without_end(&["--lines-range", "2:"])
+}
+
+#[test]
+fn dies_bad_timeout() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-f", "--timeout", "soon", EMPTY])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected a duration"));
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn header_format() -> Result<()> {
+    run_output(
+        &["--header-format", "--- {index}: {name} ({size} bytes) ---", "-n", "1", ONE, TWO],
+        &format!("--- 1: {ONE} (23 bytes) ---\nÖne line, four words.\n\n--- 2: {TWO} (23 bytes) ---\nTwo lines.\n"),
+    )
+}
+
+#[test]
+fn header_format_path() -> Result<()> {
+    let path = fs::canonicalize(ONE)?;
+    run_output(
+        &[
+            "-v",
+            "--header-format",
+            "{path} {{literal}}",
+            "-c",
+            "0",
+            ONE,
+        ],
+        &format!("{} {{literal}}\n", path.display()),
+    )
+}
+
+#[test]
+fn dies_bad_header_format() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--header-format", "{nope}", EMPTY])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown placeholder '{nope}'"));
+
+    Ok(())
+}
+
+#[test]
+fn json() -> Result<()> {
+    run_output(
+        &["--json", "-n", "2", EMPTY, TWELVE, THREE],
+        &format!(
+            "{{\"file\":\"{EMPTY}\",\"lines\":[]}}\n\
+             {{\"file\":\"{TWELVE}\",\"lines\":[\"one\",\"two\"]}}\n\
+             {{\"file\":\"{THREE}\",\"lines\":[\"Three\\r\",\"lines,\\r\"]}}\n"
+        ),
+    )
+}
+
+#[test]
+fn json_single_file_stdin() -> Result<()> {
+    run_delimited(
+        &["--json", "-n", "3"],
+        "a \"quoted\" line\n\nlast",
+        "{\"file\":\"-\",\"lines\":[\"a \\\"quoted\\\" line\",\"\",\"last\"]}\n",
+    )
+}
+
+#[test]
+fn json_skips_bad_file() -> Result<()> {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["--json", "-n", "1", &bad, TWO])
+        .assert()
+        .code(1)
+        .stdout(format!(
+            "{{\"file\":\"{TWO}\",\"lines\":[\"Two lines.\"]}}\n"
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn dies_zero_jobs() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--jobs", "0", EMPTY, ONE])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid value '0'"));
+
+    Ok(())
+}
+
+#[test]
+fn jobs_keep_argument_order() -> Result<()> {
+    let bad = gen_bad_file();
+    let files = [TWELVE, &bad, ONE, EMPTY, THREE, TWO, &bad, TWELVE, ONE];
+    for args in [["-n", "2"], ["-c", "-3"], ["--skip", "1"]] {
+        let serial = Command::cargo_bin(PRG)?
+            .args(args)
+            .arg("-j1")
+            .args(files)
+            .output()?;
+        assert_eq!(serial.status.code(), Some(1));
+        for jobs in ["-j2", "-j8"] {
+            let parallel = Command::cargo_bin(PRG)?
+                .args(args)
+                .arg(jobs)
+                .args(files)
+                .output()?;
+            assert_eq!(parallel.status.code(), Some(1));
+            assert_eq!(parallel.stdout, serial.stdout);
+            assert_eq!(parallel.stderr, serial.stderr);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn jobs_with_stdin() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-n", "1", "-j", "4", ONE, "-", TWO])
+        .write_stdin("from stdin\nnot this\n")
+        .assert()
+        .success()
+        .stdout(format!(
+            "==> {ONE} <==\nÖne line, four words.\n\n\
+             ==> - <==\nfrom stdin\n\n\
+             ==> {TWO} <==\nTwo lines.\n"
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn jobs_larger_than_prefetch() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let big = dir.path().join("big.txt");
+    let lines: String = (1..=20_000).map(|n| format!("line {n}\n")).collect();
+    fs::write(&big, &lines)?;
+    let big = big.to_str().unwrap();
+
+    // Everything but the last line, "line 20000\n"
+    let most = &lines[..lines.len() - 11];
+    run_output(
+        &["-j", "2", "-c", "-11", big, big],
+        &format!("==> {big} <==\n{most}\n==> {big} <==\n{most}"),
+    )?;
+    // Reads on from the prefetched block into the rest of the file
+    let start = &lines[..70_000];
+    run_output(
+        &["-j", "2", "-c", "70000", big, big],
+        &format!("==> {big} <==\n{start}\n==> {big} <==\n{start}"),
+    )?;
+    run_output(
+        &["-j", "2", "--skip", "19999", big, big],
+        &format!("==> {big} <==\nline 20000\n\n==> {big} <==\nline 20000\n"),
+    )
+}
+
+#[test]
+fn binary_notice() -> Result<()> {
+    run_output(
+        &["--binary=notice", ONE, BINARY],
+        &format!(
+            "==> {ONE} <==\nÖne line, four words.\n\n\
+             ==> {BINARY} <==\n\
+             headr: '{BINARY}' looks like a binary file, use --binary=hex or --binary=raw to show it\n"
+        ),
+    )
+}
+
+#[test]
+fn binary_hex() -> Result<()> {
+    run_output(
+        &["--binary=hex", "-c", "20", BINARY],
+        "00000000  7f 45 4c 46 02 01 01 00  00 00 00 00 00 00 00 00  |.ELF............|\n\
+         00000010  02 00 3e 00                                       |..>.|\n",
+    )
+}
+
+#[test]
+fn binary_hex_lines() -> Result<()> {
+    run_output(
+        &["--binary=hex", "-n", "-1", BINARY],
+        "00000000  7f 45 4c 46 02 01 01 00  00 00 00 00 00 00 00 00  |.ELF............|\n\
+         00000010  02 00 3e 00 01 00 00 00  0a                       |..>......|\n",
+    )
+}
+
+#[test]
+fn binary_hex_without_newlines() -> Result<()> {
+    // A single line that is far too long to dump in full
+    let output = Command::cargo_bin(PRG)?
+        .args(["--binary=hex", "-n", "1"])
+        .write_stdin(vec![0u8; 100_000])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 513);
+    assert!(lines[511].starts_with("00001ff0  00 00"));
+    assert_eq!(lines[512], "... 91808 more bytes not shown");
+    Ok(())
+}
+
+#[test]
+fn binary_hex_leaves_text_alone() -> Result<()> {
+    run(&["--binary=hex", ONE], "tests/expected/one.txt.out")
+}
+
+#[test]
+fn binary_stdin() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--binary=notice"])
+        .write_stdin(fs::read(BINARY)?)
+        .assert()
+        .success()
+        .stdout(
+            "headr: '-' looks like a binary file, use --binary=hex or --binary=raw to show it\n",
+        );
+
+    Ok(())
+}
+
+#[test]
+fn binary_raw_when_piped() -> Result<()> {
+    let expected = fs::read(BINARY)?;
+    for args in [&[][..], &["--binary=raw"][..]] {
+        Command::cargo_bin(PRG)?
+            .args(args)
+            .arg(BINARY)
+            .assert()
+            .success()
+            .stdout(expected.clone());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn dies_bad_binary_mode() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--binary=text", BINARY])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid value 'text'"));
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+//! ANSI colours for matches, file names, line numbers and separators, set
+//! the same way as GNU grep's through the GREP_COLORS variable.
+
+use std::io::{self, Write};
+
+/// The SGR sequence for each part of the output. An empty one leaves that
+/// part plain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Colors {
+    /// `ms`, the matched text in selected lines
+    pub matched: String,
+    /// `fn`, file names
+    pub file_name: String,
+    /// `ln`, line numbers
+    pub line_number: String,
+    /// `bn`, byte offsets
+    pub byte_offset: String,
+    /// `se`, the `:` and `-` after prefixes, and the `--` between groups
+    pub separator: String,
+}
+
+impl Default for Colors {
+    /// The same colours as GNU grep uses
+    fn default() -> Colors {
+        Colors {
+            matched: String::from("01;31"),
+            file_name: String::from("35"),
+            line_number: String::from("32"),
+            byte_offset: String::from("32"),
+            separator: String::from("36"),
+        }
+    }
+}
+
+impl Colors {
+    /// Reads a GREP_COLORS value such as `ms=01;32:fn=34:se=`, on top of the
+    /// defaults. Like GNU grep, entries that are not understood are skipped
+    /// rather than rejected.
+    pub fn parse(spec: &str) -> Colors {
+        let mut colors = Colors::default();
+        for entry in spec.split(':') {
+            let Some((key, value)) = entry.split_once('=') else {
+                continue;
+            };
+            let valid = value
+                .bytes()
+                .all(|byte| byte.is_ascii_digit() || byte == b';');
+            if !valid {
+                continue;
+            }
+            let slot = match key {
+                // mt sets the colour of matches in both selected and context
+                // lines, and only the first of those is shown
+                "ms" | "mt" => &mut colors.matched,
+                "fn" => &mut colors.file_name,
+                "ln" => &mut colors.line_number,
+                "bn" => &mut colors.byte_offset,
+                "se" => &mut colors.separator,
+                _ => continue,
+            };
+            *slot = value.to_string();
+        }
+        colors
+    }
+}
+
+/// Writes [text] in the colour [sgr], or plain when there is no colour.
+pub fn paint(out: &mut dyn Write, sgr: Option<&str>, text: &[u8]) -> io::Result<()> {
+    match sgr {
+        Some(sgr) if !sgr.is_empty() => {
+            // The erase to the end of line keeps a background colour from
+            // running on past the text
+            write!(out, "\x1b[{}m\x1b[K", sgr)?;
+            out.write_all(text)?;
+            out.write_all(b"\x1b[m\x1b[K")
+        }
+        _ => out.write_all(text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(Colors::parse(""), Colors::default());
+        let colors = Colors::parse("ms=01;32:fn=:ne:xx=1:ln=33:se=bad");
+        assert_eq!(colors.matched, "01;32");
+        assert_eq!(colors.file_name, "");
+        assert_eq!(colors.line_number, "33");
+        assert_eq!(colors.separator, "36");
+    }
+
+    #[test]
+    fn painting() {
+        let mut out = Vec::new();
+        paint(&mut out, Some("01;31"), b"hit").unwrap();
+        paint(&mut out, Some(""), b" plain").unwrap();
+        paint(&mut out, None, b" too").unwrap();
+        assert_eq!(out, b"\x1b[01;31m\x1b[Khit\x1b[m\x1b[K plain too");
+    }
+}
@@ -1,51 +1,576 @@
 use std::{
-    env,
-    fs,
+    fs::File,
     error::Error,
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Write},
+    ops::Range,
+    path::Path,
 };
 
+use regex::bytes::Regex;
+
+pub mod color;
+mod parallel;
+pub mod pattern;
+pub mod walk;
+
+use color::{paint, Colors};
+use pattern::Syntax;
+use walk::Walker;
+
 
 pub struct Config { // Struct for the user input
     pub query: String,
-    pub file_name: String,
-    pub ignore_case: bool
+    pub file_names: Vec<String>,
+    pub ignore_case: bool,
+    pub syntax: Syntax,
+    pub mode: Mode,
+    pub invert: bool,
+    pub line_number: bool,
+    pub byte_offset: bool,
+    pub only_matching: bool,
+    pub max_count: Option<u64>,
+    pub before_context: usize,
+    pub after_context: usize,
+    pub recursive: bool,
+    pub hidden: bool,
+    pub no_ignore: bool,
+    pub globs: Vec<String>,
+    pub types: Vec<String>,
+    pub threads: usize,
+    pub sort_by_path: bool,
+    /// The colours to use, or None for plain output
+    pub colors: Option<Colors>,
 }
 
-impl Config {
-    pub fn build(args: &[String]) -> Result<Config, &'static str> {
-        if args.len() < 3 {
-            Err::<Config, &'static str>("Not enough arguments")
-        } else {
-            // Need to make copies, since it cannot take ownership.
-            let query = args[1].to_owned();
-            let file_name = args[2].to_owned();
-            
-            let ignore_case = env::var("IGNORE_CASE").is_ok();
+/// What gets printed for the lines that are selected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// The lines themselves
+    Lines,
+    /// How many lines were selected in each file
+    Count,
+    /// The names of the files with at least one selected line
+    FilesWithMatches,
+    /// The names of the files with no selected line
+    FilesWithoutMatch,
+    /// Nothing, only the exit code tells whether anything was selected
+    Quiet,
+}
 
-            Ok(Config {query, file_name, ignore_case})   // Can use the constructor
+/// How a run went, which decides the exit code
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// Whether any line was selected
+    pub matched: bool,
+    /// Whether some file could not be read
+    pub had_errors: bool,
+}
+
+impl Summary {
+    /// The POSIX exit code: 0 when something matched, 1 when nothing did
+    /// and 2 on errors. A match found in quiet mode wins over errors.
+    pub fn exit_code(&self, quiet: bool) -> i32 {
+        match (self.matched, self.had_errors) {
+            (true, true) if quiet => 0,
+            (_, true) => 2,
+            (true, false) => 0,
+            (false, false) => 1,
         }
     }
-    pub fn read_file(&self) -> String {
-        fs::read_to_string(&self.file_name).unwrap()
+}
+
+/// Opens [file_name] to be read line by line, where "-" stands for standard
+/// input.
+pub fn open(file_name: &str) -> io::Result<Box<dyn BufRead>> {
+    if file_name == "-" {
+        Ok(Box::new(io::stdin().lock()))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(file_name)?)))
     }
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let content = config.read_file();   // Method for reading the file
-    let query = config.query;
+/// One line of input, without its newline
+#[derive(Debug, PartialEq, Eq)]
+pub struct Line<'a> {
+    /// The line number, counting from 1
+    pub number: u64,
+    /// Where the line starts in the input, in bytes
+    pub offset: u64,
+    pub bytes: &'a [u8],
+}
+
+/// Reads lines one at a time into a buffer that is reused, so memory use is
+/// bounded by the longest line rather than the size of the input. Lines are
+/// bytes, as nothing says the input is UTF-8.
+pub struct Lines<R> {
+    reader: R,
+    buf: Vec<u8>,
+    number: u64,
+    offset: u64,
+}
 
-    // Using iteration to print out all the collected search
-    let string_matches = if config.ignore_case {
-        search_case_insensitive(&query, &content)
+impl<R: BufRead> Lines<R> {
+    pub fn new(reader: R) -> Lines<R> {
+        Lines {
+            reader,
+            buf: Vec::new(),
+            number: 0,
+            offset: 0,
+        }
+    }
+
+    /// Gives the next line, or None at the end of the input.
+    pub fn next_line(&mut self) -> io::Result<Option<Line<'_>>> {
+        self.buf.clear();
+        let len = self.reader.read_until(b'\n', &mut self.buf)?;
+        if len == 0 {
+            return Ok(None);
+        }
+        let offset = self.offset;
+        self.number += 1;
+        self.offset += len as u64;
+        if self.buf.last() == Some(&b'\n') {
+            self.buf.pop();
+        }
+        Ok(Some(Line {
+            number: self.number,
+            offset,
+            bytes: &self.buf,
+        }))
+    }
+}
+
+/// The name to show for [file_name] in messages and match prefixes.
+pub fn display_name(file_name: &str) -> &str {
+    if file_name == "-" {
+        "(standard input)"
     } else {
-        search(&query, &content)
+        file_name
+    }
+}
+
+/// Searches every file in the config, printing what the mode asks for as
+/// soon as it is known. Lines are prefixed with their file name when there
+/// is more than one file, or a directory is searched. A file that cannot be
+/// read is reported and skipped. Only failing to write the output stops the
+/// run with an error.
+pub fn run(config: Config) -> Result<Summary, Box<dyn Error>> {
+    let regex = pattern::build(&config.query, config.syntax, config.ignore_case)?;
+    let walker = Walker::new(&config)?;
+
+    // With no files, -r searches the current directory, and the paths found
+    // there are shown without a leading "./"
+    let implicit = config.file_names.is_empty();
+    let operands = match (implicit, config.recursive) {
+        (true, true) => vec![String::from(".")],
+        (true, false) => vec![String::from("-")],
+        (false, _) => config.file_names.clone(),
+    };
+    let search = Search {
+        config: &config,
+        regex: &regex,
+        walker: &walker,
+        operands: &operands,
+        implicit,
+        show_names: operands.len() > 1 || operands.iter().any(|operand| is_walked(&config, operand)),
     };
 
-    string_matches.into_iter().for_each(|ele| println!("{ele}"));
+    // A single file is streamed straight to the output, as there is nothing
+    // for other threads to do
+    let single = operands.len() == 1 && !is_walked(&config, &operands[0]);
+    let summary = if config.threads <= 1 || single {
+        search.run_sequential()?
+    } else {
+        search.run_parallel()?
+    };
+    Ok(summary)
+}
+
+/// Whether [operand] is a directory to walk rather than a file to search
+fn is_walked(config: &Config, operand: &str) -> bool {
+    config.recursive && operand != "-" && Path::new(operand).is_dir()
+}
+
+/// One file to search, or a problem met while looking for files
+enum Source {
+    File { name: String, walked: bool },
+    WalkError(String),
+}
+
+/// What searching one file came to
+#[derive(Default)]
+struct Outcome {
+    /// Whether a line was selected
+    matched: bool,
+    had_errors: bool,
+    /// The error messages for the file, to go to standard error
+    messages: Vec<String>,
+}
+
+/// Everything a run needs, shared by all the threads searching
+struct Search<'a> {
+    config: &'a Config,
+    regex: &'a Regex,
+    walker: &'a Walker,
+    operands: &'a [String],
+    implicit: bool,
+    show_names: bool,
+}
+
+impl Search<'_> {
+    /// Searches the files one after another, writing to the output as lines
+    /// are found.
+    fn run_sequential(&self) -> io::Result<Summary> {
+        let mut summary = Summary::default();
+        let mut printed_any = false;
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+
+        self.for_each_source(true, |source| {
+            let outcome = match source {
+                Source::File { name, walked } => {
+                    self.search_one(&name, walked, &mut printed_any, &mut out)?
+                }
+                Source::WalkError(message) => self.walk_error(message),
+            };
+            Ok(self.report(&mut summary, outcome))
+        })?;
+        Ok(summary)
+    }
+
+    /// Finds the files to search, in order, and hands each to [each] until it
+    /// asks to stop by returning true. Directories are walked in order of
+    /// file name when [sorted] is set.
+    fn for_each_source(
+        &self,
+        sorted: bool,
+        mut each: impl FnMut(Source) -> io::Result<bool>,
+    ) -> io::Result<()> {
+        for operand in self.operands {
+            if !is_walked(self.config, operand) {
+                let source = Source::File {
+                    name: operand.clone(),
+                    walked: false,
+                };
+                if each(source)? {
+                    return Ok(());
+                }
+                continue;
+            }
+            let entries = match self.walker.walk(operand, sorted) {
+                Ok(entries) => entries,
+                Err(err) => {
+                    if each(Source::WalkError(err.to_string()))? {
+                        return Ok(());
+                    }
+                    continue;
+                }
+            };
+            for entry in entries {
+                let source = match entry {
+                    Ok(entry) if entry.file_type().is_some_and(|file_type| file_type.is_file()) => {
+                        let path = match self.implicit {
+                            true => entry.path().strip_prefix(".").unwrap_or(entry.path()),
+                            false => entry.path(),
+                        };
+                        Source::File {
+                            name: path.to_string_lossy().into_owned(),
+                            walked: true,
+                        }
+                    }
+                    Ok(_) => continue,
+                    Err(err) => Source::WalkError(err.to_string()),
+                };
+                if each(source)? {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Searches one file, writing what the mode asks for to [out].
+    /// [printed_any] tells whether lines were printed before this file.
+    fn search_one(
+        &self,
+        file_name: &str,
+        walked: bool,
+        printed_any: &mut bool,
+        out: &mut dyn Write,
+    ) -> io::Result<Outcome> {
+        let config = self.config;
+        let mut outcome = Outcome::default();
+        let mut reader = match open(file_name) {
+            Ok(reader) => reader,
+            Err(err) => {
+                outcome.messages.push(format!("{}: {}", display_name(file_name), err));
+                outcome.had_errors = true;
+                return Ok(outcome);
+            }
+        };
+        // Files found by walking are only searched when they look like text.
+        // A read error here shows up again once the search reads the file.
+        if walked && looks_binary(&mut reader).unwrap_or(false) {
+            return Ok(outcome);
+        }
+
+        let prefix = self.show_names.then(|| display_name(file_name));
+        let lines = Lines::new(reader);
+        let searched = search_file(config, self.regex, prefix, lines, printed_any, out)?;
+        if let Some(err) = &searched.read_error {
+            // Whatever was found before the error has been printed
+            outcome.messages.push(format!("{}: {}", display_name(file_name), err));
+            outcome.had_errors = true;
+        }
+
+        match config.mode {
+            Mode::Count => {
+                write_prefix(config, prefix, None, None, b':', out)?;
+                writeln!(out, "{}", searched.count)?
+            }
+            Mode::FilesWithMatches if searched.count > 0 => {
+                write_name(config, display_name(file_name), out)?
+            }
+            Mode::FilesWithoutMatch if searched.count == 0 && searched.read_error.is_none() => {
+                write_name(config, display_name(file_name), out)?
+            }
+            _ => (),
+        }
+        // Like GNU grep, -L still succeeds on a selected line rather than on
+        // a file listed
+        outcome.matched = searched.count > 0;
+        Ok(outcome)
+    }
 
+    fn walk_error(&self, message: String) -> Outcome {
+        Outcome {
+            matched: false,
+            had_errors: true,
+            messages: vec![message],
+        }
+    }
+
+    /// Prints the messages for one file and adds its outcome to [summary].
+    /// Tells whether the run can stop, as nothing more can change the
+    /// outcome once quiet mode has a match. Errors are reported even in
+    /// quiet mode, which only keeps standard output empty.
+    fn report(&self, summary: &mut Summary, outcome: Outcome) -> bool {
+        for message in &outcome.messages {
+            eprintln!("rust-grep: {}", message);
+        }
+        summary.matched |= outcome.matched;
+        summary.had_errors |= outcome.had_errors;
+        self.config.mode == Mode::Quiet && outcome.matched
+    }
+}
+
+/// Guesses whether [reader] holds binary data, from a NUL byte in the part
+/// of it that is already buffered. Nothing is consumed.
+pub fn looks_binary(reader: &mut dyn BufRead) -> io::Result<bool> {
+    Ok(reader.fill_buf()?.contains(&0))
+}
+
+/// The result of searching one file
+struct Searched {
+    /// How many lines were selected
+    count: u64,
+    /// The error that cut reading the file short, if any
+    read_error: Option<io::Error>,
+}
+
+/// Goes through [lines], printing the selected ones in Lines mode along
+/// with any context asked for. Reading stops early once the mode has all it
+/// needs, or at the --max-count once its trailing context is printed.
+/// [printed_any] tells whether an earlier file printed lines, so that the
+/// first group here gets a separator too.
+fn search_file<R: BufRead>(
+    config: &Config,
+    regex: &Regex,
+    prefix: Option<&str>,
+    mut lines: Lines<R>,
+    printed_any: &mut bool,
+    out: &mut dyn Write,
+) -> io::Result<Searched> {
+    let max_count = match config.mode {
+        Mode::Lines | Mode::Count => config.max_count.unwrap_or(u64::MAX),
+        _ => 1,
+    };
+    // Only whole lines have context around them
+    let show_context = config.mode == Mode::Lines && !config.only_matching;
+    let (before_context, after_context) = match show_context {
+        true => (config.before_context, config.after_context),
+        false => (0, 0),
+    };
+    let separate = before_context > 0 || after_context > 0;
+
+    let mut count = 0;
+    let mut before: VecDeque<(u64, u64, Vec<u8>)> = VecDeque::with_capacity(before_context);
+    let mut after_left = 0;
+    let mut last_printed: Option<u64> = None;
+    // Writes the "--" that goes between groups of lines that are not
+    // next to each other, then notes [number] as printed
+    let mut start_line = |number: u64, out: &mut dyn Write| -> io::Result<()> {
+        let gap = match last_printed {
+            Some(last) => number != last + 1,
+            None => *printed_any,
+        };
+        if separate && gap {
+            write_separator(config, out)?;
+        }
+        last_printed = Some(number);
+        *printed_any = true;
+        Ok(())
+    };
+
+    while count < max_count || after_left > 0 {
+        let line = match lines.next_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                return Ok(Searched {
+                    count,
+                    read_error: Some(err),
+                })
+            }
+        };
+        let selected = count < max_count && regex.is_match(line.bytes) != config.invert;
+        if selected {
+            count += 1;
+            if config.mode == Mode::Lines {
+                for (number, offset, bytes) in before.drain(..) {
+                    start_line(number, out)?;
+                    write_context(config, prefix, number, offset, &bytes, out)?;
+                }
+                start_line(line.number, out)?;
+                write_line(config, regex, prefix, &line, out)?;
+                after_left = after_context;
+            }
+        } else if after_left > 0 {
+            after_left -= 1;
+            start_line(line.number, out)?;
+            write_context(config, prefix, line.number, line.offset, line.bytes, out)?;
+        } else if before_context > 0 {
+            if before.len() == before_context {
+                before.pop_front();
+            }
+            before.push_back((line.number, line.offset, line.bytes.to_vec()));
+        }
+    }
+    Ok(Searched {
+        count,
+        read_error: None,
+    })
+}
+
+/// Finds where [regex] matches in [line], as byte ranges. Empty matches
+/// are left out, as there is nothing in them to show.
+pub fn match_ranges(regex: &Regex, line: &[u8]) -> Vec<Range<usize>> {
+    regex
+        .find_iter(line)
+        .filter(|found| !found.is_empty())
+        .map(|found| found.range())
+        .collect()
+}
+
+/// The colour [pick] chooses for a part of the output, when the output is
+/// coloured
+fn color<'a>(config: &'a Config, pick: impl Fn(&'a Colors) -> &'a String) -> Option<&'a str> {
+    config.colors.as_ref().map(|colors| pick(colors).as_str())
+}
+
+/// Prints a selected line, or with --only-matching, each match in it on a
+/// line of its own. Matches are highlighted when the output is coloured.
+fn write_line(
+    config: &Config,
+    regex: &Regex,
+    prefix: Option<&str>,
+    line: &Line,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let matched = color(config, |colors| &colors.matched);
+    if !config.only_matching {
+        write_prefix(config, prefix, Some(line.number), Some(line.offset), b':', out)?;
+        // Inverted lines have no matches in them to highlight
+        if matched.is_none() || config.invert {
+            out.write_all(line.bytes)?;
+            return out.write_all(b"\n");
+        }
+        let mut end = 0;
+        for range in match_ranges(regex, line.bytes) {
+            out.write_all(&line.bytes[end..range.start])?;
+            paint(out, matched, &line.bytes[range.clone()])?;
+            end = range.end;
+        }
+        out.write_all(&line.bytes[end..])?;
+        return out.write_all(b"\n");
+    }
+    if config.invert {
+        return Ok(());
+    }
+    for range in match_ranges(regex, line.bytes) {
+        let offset = line.offset + range.start as u64;
+        write_prefix(config, prefix, Some(line.number), Some(offset), b':', out)?;
+        paint(out, matched, &line.bytes[range])?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Prints a line of context, marked with '-' where a selected line has ':'.
+fn write_context(
+    config: &Config,
+    prefix: Option<&str>,
+    number: u64,
+    offset: u64,
+    bytes: &[u8],
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    write_prefix(config, prefix, Some(number), Some(offset), b'-', out)?;
+    out.write_all(bytes)?;
+    out.write_all(b"\n")
+}
+
+/// Writes the file name, line number and byte offset that go in front of a
+/// line, as far as they were asked for, each followed by [marker].
+fn write_prefix(
+    config: &Config,
+    prefix: Option<&str>,
+    number: Option<u64>,
+    offset: Option<u64>,
+    marker: u8,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let separator = color(config, |colors| &colors.separator);
+    if let Some(name) = prefix {
+        paint(out, color(config, |colors| &colors.file_name), name.as_bytes())?;
+        paint(out, separator, &[marker])?;
+    }
+    if let Some(number) = number.filter(|_| config.line_number) {
+        let number = number.to_string();
+        paint(out, color(config, |colors| &colors.line_number), number.as_bytes())?;
+        paint(out, separator, &[marker])?;
+    }
+    if let Some(offset) = offset.filter(|_| config.byte_offset) {
+        let offset = offset.to_string();
+        paint(out, color(config, |colors| &colors.byte_offset), offset.as_bytes())?;
+        paint(out, separator, &[marker])?;
+    }
     Ok(())
 }
 
+/// Writes a file name on a line of its own, for -l and -L.
+fn write_name(config: &Config, name: &str, out: &mut dyn Write) -> io::Result<()> {
+    paint(out, color(config, |colors| &colors.file_name), name.as_bytes())?;
+    out.write_all(b"\n")
+}
+
+/// Writes the "--" line that goes between groups of lines with context.
+fn write_separator(config: &Config, out: &mut dyn Write) -> io::Result<()> {
+    paint(out, color(config, |colors| &colors.separator), b"--")?;
+    out.write_all(b"\n")
+}
+
 pub fn search<'a>(query: &str, content: &'a str) -> Vec<&'a str> {
     // This method is the searching
     let mut ret: Vec<&str> = Vec::new();
@@ -96,4 +621,22 @@ Trust me!
         assert_eq!(vec!["Rust:", "Trust me!"], search_case_insensitive(
                 query, CONTENT));
     }
+
+    #[test]
+    fn lines() {
+        let mut lines = Lines::new(&b"one\n\xfftwo\n\nlast"[..]);
+        let mut next = || lines.next_line().unwrap().map(|line| (line.number, line.offset, line.bytes.to_vec()));
+        assert_eq!(next(), Some((1, 0, b"one".to_vec())));
+        assert_eq!(next(), Some((2, 4, b"\xfftwo".to_vec())));
+        assert_eq!(next(), Some((3, 9, b"".to_vec())));
+        assert_eq!(next(), Some((4, 10, b"last".to_vec())));
+        assert_eq!(next(), None);
+    }
+
+    #[test]
+    fn ranges() {
+        let regex = Regex::new("o+|x*").unwrap();
+        assert_eq!(match_ranges(&regex, b"foo boo"), vec![1..3, 5..7]);
+        assert!(match_ranges(&regex, b"abc").is_empty());
+    }
 }
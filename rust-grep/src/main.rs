@@ -1,33 +1,218 @@
-use std::{env, process};
+use std::{
+    env,
+    io::{self, IsTerminal},
+    process, thread,
+};
 
 use clap::Parser;
 
-use rust_grep::{run, Config};
+use rust_grep::{color::Colors, pattern::Syntax, run, Config, Mode};
 
+/// Search for PATTERN in each FILE, printing the lines that match it
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
-struct Args {}
+struct Args {
+    /// The pattern to search for, a basic regular expression unless -E or
+    /// -F is given
+    pattern: String,
+
+    /// Files to search, where '-' is standard input. Standard input is
+    /// searched when no FILE is given, or the current directory with -r
+    #[arg(value_name = "FILE")]
+    files: Vec<String>,
+
+    /// Ignore case distinctions. This is the default when the IGNORE_CASE
+    /// environment variable is set
+    #[arg(short, long, overrides_with = "no_ignore_case")]
+    ignore_case: bool,
+
+    /// Match case exactly, even when IGNORE_CASE is set
+    #[arg(long, overrides_with = "ignore_case")]
+    no_ignore_case: bool,
+
+    /// Read PATTERN as an extended regular expression, where ( ) { } | + ?
+    /// are special without a backslash
+    #[arg(short = 'E', long, conflicts_with = "fixed_strings")]
+    extended_regexp: bool,
+
+    /// Read PATTERN as a plain string rather than a regular expression
+    #[arg(short = 'F', long)]
+    fixed_strings: bool,
+
+    /// Select the lines that do not match
+    #[arg(short = 'v', long)]
+    invert_match: bool,
+
+    /// Print only how many lines were selected in each file
+    #[arg(short, long)]
+    count: bool,
+
+    /// Print only the names of the files with selected lines
+    #[arg(short = 'l', long, conflicts_with_all = ["count", "files_without_match"])]
+    files_with_matches: bool,
+
+    /// Print only the names of the files without selected lines
+    #[arg(short = 'L', long, conflicts_with = "count")]
+    files_without_match: bool,
+
+    /// Put the line number in front of each line
+    #[arg(short = 'n', long)]
+    line_number: bool,
+
+    /// Put the byte offset in the file in front of each line, or with -o,
+    /// in front of each match
+    #[arg(short, long)]
+    byte_offset: bool,
+
+    /// Print each match on a line of its own, rather than the whole line
+    #[arg(short, long)]
+    only_matching: bool,
+
+    /// Print nothing, and exit with 0 as soon as anything matches
+    #[arg(short, long, visible_alias = "silent")]
+    quiet: bool,
+
+    /// Stop reading a file after NUM selected lines
+    #[arg(short, long, value_name = "NUM")]
+    max_count: Option<u64>,
+
+    /// Print NUM lines of context after each selected line
+    #[arg(short = 'A', long, value_name = "NUM")]
+    after_context: Option<usize>,
+
+    /// Print NUM lines of context before each selected line
+    #[arg(short = 'B', long, value_name = "NUM")]
+    before_context: Option<usize>,
+
+    /// Print NUM lines of context around each selected line, unless -A or
+    /// -B say otherwise. Groups of lines that are not next to each other
+    /// are split by a "--" line
+    #[arg(short = 'C', long, value_name = "NUM")]
+    context: Option<usize>,
+
+    /// Search the files under each directory, leaving out hidden files,
+    /// binary files and whatever .gitignore, .ignore or .git/info/exclude
+    /// ignore
+    #[arg(short, long)]
+    recursive: bool,
+
+    /// With -r, search hidden files and directories too
+    #[arg(long)]
+    hidden: bool,
+
+    /// With -r, do not leave out the files that ignore files list
+    #[arg(long)]
+    no_ignore: bool,
+
+    /// With -r, only search files matching GLOB, or with a leading '!', not
+    /// matching it. May be given more than once
+    #[arg(short, long, value_name = "GLOB")]
+    glob: Vec<String>,
+
+    /// With -r, only search files of TYPE, such as 'rust' or 'md'. May be
+    /// given more than once
+    #[arg(short, long = "type", value_name = "TYPE")]
+    r#type: Vec<String>,
+
+    /// Search NUM files at once. Defaults to the number of CPUs
+    #[arg(short = 'j', long, value_name = "NUM", value_parser = clap::value_parser!(u64).range(1..))]
+    threads: Option<u64>,
+
+    /// With 'path', print files in the order they are found, by path within
+    /// each directory. With 'none', print first whichever file finds
+    /// something first
+    #[arg(long, value_name = "SORTBY", value_parser = ["none", "path"], default_value = "none")]
+    sort: String,
+
+    /// Colour matches, file names, line numbers and separators: 'always',
+    /// 'never', or with 'auto', only when writing to a terminal. The colours
+    /// can be changed through GREP_COLORS, as with GNU grep
+    #[arg(long, visible_alias = "colour", value_name = "WHEN", value_parser = ["auto", "always", "never"], default_value = "auto")]
+    color: String,
+}
 
 /*
  *  Planning
  *  1. Read the user arguments (Done!)
  *  2. Open the specified file (Done!)
- *  3. Run the regex
+ *  3. Run the regex (Done!)
  *  4. Return the output
  */
 
+/// Whether --color=[when] means colouring the output
+fn use_color(when: &str) -> bool {
+    match when {
+        "always" => true,
+        "never" => false,
+        // A dumb terminal shows escape sequences as they are
+        _ => io::stdout().is_terminal() && env::var("TERM").map_or(true, |term| term != "dumb"),
+    }
+}
+
 // Program entry point
 fn main() {
-    let args: Vec<String> = env::args().collect(); // getting cmd line args
+    let args = Args::parse(); // getting cmd line args
+
+    // The environment variable only decides when neither flag was given
+    let ignore_case = if args.ignore_case {
+        true
+    } else if args.no_ignore_case {
+        false
+    } else {
+        env::var("IGNORE_CASE").is_ok()
+    };
+
+    let mode = if args.quiet {
+        Mode::Quiet
+    } else if args.files_with_matches {
+        Mode::FilesWithMatches
+    } else if args.files_without_match {
+        Mode::FilesWithoutMatch
+    } else if args.count {
+        Mode::Count
+    } else {
+        Mode::Lines
+    };
 
-    let config = Config::build(&args).unwrap_or_else(|err| {
-        eprintln!("Parsing error: {}", err);
-        process::exit(1)
-    });
+    let config = Config {
+        query: args.pattern,
+        file_names: args.files,
+        ignore_case,
+        syntax: if args.extended_regexp {
+            Syntax::Extended
+        } else if args.fixed_strings {
+            Syntax::Fixed
+        } else {
+            Syntax::Basic
+        },
+        mode,
+        invert: args.invert_match,
+        line_number: args.line_number,
+        byte_offset: args.byte_offset,
+        only_matching: args.only_matching,
+        max_count: args.max_count,
+        before_context: args.before_context.or(args.context).unwrap_or(0),
+        after_context: args.after_context.or(args.context).unwrap_or(0),
+        recursive: args.recursive,
+        hidden: args.hidden,
+        no_ignore: args.no_ignore,
+        globs: args.glob,
+        types: args.r#type,
+        threads: match args.threads {
+            Some(threads) => threads as usize,
+            None => thread::available_parallelism().map_or(1, |threads| threads.get()),
+        },
+        sort_by_path: args.sort == "path",
+        colors: use_color(&args.color)
+            .then(|| Colors::parse(&env::var("GREP_COLORS").unwrap_or_default())),
+    };
 
-    if let Err(x) = run(config) {
+    match run(config) {
         // Running the main program here.
-        eprintln!("Application Error: {}", x);
-        process::exit(1)
+        Ok(summary) => process::exit(summary.exit_code(args.quiet)),
+        Err(x) => {
+            eprintln!("Application Error: {}", x);
+            process::exit(2)
+        }
     };
 }
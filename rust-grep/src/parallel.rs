@@ -0,0 +1,273 @@
+//! Searching files on a pool of threads. One file at a time has its turn at
+//! the output and writes straight to it, as in a sequential search, while
+//! the others hold what they find in a bounded buffer until their turn.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    sync::{mpsc, Condvar, Mutex, MutexGuard},
+    thread,
+};
+
+use crate::{write_separator, Mode, Outcome, Search, Source, Summary};
+
+/// How much output a file may hold while waiting for its turn. Past that,
+/// its search waits too.
+const SPOOL_LIMIT: usize = 64 * 1024;
+
+impl Search<'_> {
+    /// Searches the files on a pool of threads, without mixing the lines of
+    /// different files. With --sort path, files take their turn in the order
+    /// they were found, otherwise the first to have output goes first.
+    /// Memory use is bounded, as only as many files as there are threads may
+    /// wait for their turn once finished.
+    pub(crate) fn run_parallel(&self) -> io::Result<Summary> {
+        let config = self.config;
+        let turns = Turns {
+            search: self,
+            sorted: config.sort_by_path,
+            separate: config.mode == Mode::Lines
+                && !config.only_matching
+                && (config.before_context > 0 || config.after_context > 0),
+            parked_limit: config.threads,
+            state: Mutex::new(State::default()),
+            changed: Condvar::new(),
+        };
+        let (job_tx, job_rx) = mpsc::channel::<(usize, Source)>();
+        let job_rx = &Mutex::new(job_rx);
+
+        thread::scope(|scope| {
+            let turns = &turns;
+            for _ in 0..config.threads {
+                scope.spawn(move || loop {
+                    // The lock is only held while waiting for a job. Safe
+                    // unwrap, a worker only panics if the search does.
+                    let job = job_rx.lock().unwrap().recv();
+                    let Ok((index, source)) = job else {
+                        break;
+                    };
+                    if turns.lock().done {
+                        break;
+                    }
+                    let mut spool = Spool {
+                        turns,
+                        index,
+                        buf: Vec::new(),
+                        writing: false,
+                    };
+                    let outcome = match source {
+                        Source::File { name, walked } => {
+                            self.search_one(&name, walked, &mut false, &mut spool)
+                        }
+                        Source::WalkError(message) => Ok(self.walk_error(message)),
+                    };
+                    if let Err(err) = outcome.and_then(|outcome| turns.finish(spool, outcome)) {
+                        turns.fail(err);
+                        break;
+                    }
+                });
+            }
+
+            let mut index = 0;
+            self.for_each_source(config.sort_by_path, |source| {
+                if turns.lock().done || job_tx.send((index, source)).is_err() {
+                    return Ok(true);
+                }
+                index += 1;
+                Ok(false)
+            })?;
+            // Hanging up lets the workers finish once the jobs run out
+            drop(job_tx);
+            io::Result::Ok(())
+        })?;
+
+        // Safe unwrap, as above
+        let state = turns.state.into_inner().unwrap();
+        match state.error {
+            Some(err) => Err(err),
+            None => Ok(state.summary),
+        }
+    }
+}
+
+/// Which file has its turn at the output, shared by the threads searching
+struct Turns<'s, 'a> {
+    search: &'s Search<'a>,
+    sorted: bool,
+    /// Whether groups of lines from different files get a "--" between them
+    separate: bool,
+    /// How many finished files may wait for their turn
+    parked_limit: usize,
+    state: Mutex<State>,
+    /// Signalled whenever a turn ends or the run stops
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    /// The file writing straight to the output, if any
+    owner: Option<usize>,
+    /// With --sort path, the file whose turn comes next
+    next: usize,
+    /// Files that finished before their turn, with what they found
+    parked: BTreeMap<usize, (Outcome, Vec<u8>)>,
+    summary: Summary,
+    printed_any: bool,
+    /// Set once nothing more is to be printed, because quiet mode has a
+    /// match or writing the output failed
+    done: bool,
+    error: Option<io::Error>,
+}
+
+impl Turns<'_, '_> {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // Safe unwrap, the lock is never held across anything that panics
+        self.state.lock().unwrap()
+    }
+
+    /// Whether the file at [index] may write to the output now
+    fn may_write(&self, state: &State, index: usize) -> bool {
+        state.owner.is_none() && (!self.sorted || state.next == index)
+    }
+
+    /// Waits until the file at [index] may write to the output, then gives
+    /// it the turn. Fails if the run stops first.
+    fn take_turn<'g>(
+        &'g self,
+        mut state: MutexGuard<'g, State>,
+        index: usize,
+    ) -> io::Result<MutexGuard<'g, State>> {
+        while !self.may_write(&state, index) {
+            if state.done {
+                return Err(stopped());
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+        state.owner = Some(index);
+        Ok(state)
+    }
+
+    /// Hands in what searching the file at [spool]'s index came to. It is
+    /// printed now if it is that file's turn, or else parked until it is.
+    fn finish(&self, spool: Spool, outcome: Outcome) -> io::Result<()> {
+        let index = spool.index;
+        let mut state = self.lock();
+        if spool.writing {
+            self.report(&mut state, outcome);
+            return self.pass_turn(state, index);
+        }
+        while !self.may_write(&state, index) {
+            if state.done {
+                return Ok(());
+            }
+            if state.parked.len() < self.parked_limit {
+                state.parked.insert(index, (outcome, spool.buf));
+                return Ok(());
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+        state.owner = Some(index);
+        self.print(&mut state, outcome, &spool.buf)?;
+        self.pass_turn(state, index)
+    }
+
+    /// Ends the turn of the file at [index], first printing the parked files
+    /// that come next.
+    fn pass_turn(&self, mut state: MutexGuard<State>, index: usize) -> io::Result<()> {
+        state.next = index + 1;
+        while !state.done {
+            let parked = match self.sorted {
+                true => {
+                    let next = state.next;
+                    state.parked.remove(&next)
+                }
+                false => state.parked.pop_first().map(|(_, parked)| parked),
+            };
+            let Some((outcome, buf)) = parked else {
+                break;
+            };
+            self.print(&mut state, outcome, &buf)?;
+            state.next += 1;
+        }
+        state.owner = None;
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// Prints a whole file's output while it has the turn, and reports it
+    fn print(&self, state: &mut State, outcome: Outcome, buf: &[u8]) -> io::Result<()> {
+        if !buf.is_empty() {
+            let mut out = io::stdout().lock();
+            if self.separate && state.printed_any {
+                write_separator(self.search.config, &mut out)?;
+            }
+            state.printed_any = true;
+            out.write_all(buf)?;
+        }
+        self.report(state, outcome);
+        Ok(())
+    }
+
+    fn report(&self, state: &mut State, outcome: Outcome) {
+        if self.search.report(&mut state.summary, outcome) {
+            state.done = true;
+        }
+    }
+
+    /// Stops the run on the first error, which is then what it returns
+    fn fail(&self, err: io::Error) {
+        let mut state = self.lock();
+        if !state.done {
+            state.error = Some(err);
+            state.done = true;
+        }
+        self.changed.notify_all();
+    }
+}
+
+/// The error a file's search gets when the run stops before its turn
+fn stopped() -> io::Error {
+    io::Error::other("the search was stopped")
+}
+
+/// Where one file's output goes: into a buffer until the file has its turn,
+/// then straight to the output
+struct Spool<'t, 's, 'a> {
+    turns: &'t Turns<'s, 'a>,
+    index: usize,
+    buf: Vec<u8>,
+    /// Whether the file has its turn
+    writing: bool,
+}
+
+impl Write for Spool<'_, '_, '_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if !self.writing {
+            let state = self.turns.lock();
+            let fits = self.buf.len() + data.len() <= SPOOL_LIMIT;
+            if fits && !self.turns.may_write(&state, self.index) {
+                self.buf.extend_from_slice(data);
+                return Ok(data.len());
+            }
+            let mut state = self.turns.take_turn(state, self.index)?;
+            let mut out = io::stdout().lock();
+            if self.turns.separate && state.printed_any {
+                write_separator(self.turns.search.config, &mut out)?;
+            }
+            state.printed_any = true;
+            drop(state);
+            out.write_all(&self.buf)?;
+            self.buf = Vec::new();
+            self.writing = true;
+        }
+        io::stdout().write_all(data)?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.writing {
+            true => io::stdout().flush(),
+            false => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! Turning the user's pattern into a regular expression, for each of the
+//! pattern syntaxes grep knows about.
+
+use std::{error::Error, fmt};
+
+use regex::bytes::{Regex, RegexBuilder};
+
+/// How the pattern given on the command line is read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// POSIX basic regular expressions, where `\(`, `\{`, `\|`, `\+` and
+    /// `\?` are the special forms and the bare characters are literal
+    Basic,
+    /// Extended regular expressions, as understood by the regex crate
+    Extended,
+    /// A plain string with nothing special in it
+    Fixed,
+}
+
+/// A pattern that could not be compiled, along with where in the pattern the
+/// problem is when that is known.
+#[derive(Debug)]
+pub struct PatternError {
+    message: String,
+    pattern: String,
+    span: Option<(usize, usize)>,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid pattern: {}", self.message)?;
+        if let Some((start, end)) = self.span {
+            // Count characters, not bytes, so the marker lines up
+            let indent = self.pattern[..start].chars().count();
+            let width = self.pattern[start..end].chars().count().max(1);
+            write!(
+                f,
+                "\n    {}\n    {}{}",
+                self.pattern,
+                " ".repeat(indent),
+                "^".repeat(width)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for PatternError {}
+
+/// Compiles [pattern], read with [syntax], into a regular expression that
+/// works on bytes, so input need not be valid UTF-8.
+pub fn build(pattern: &str, syntax: Syntax, ignore_case: bool) -> Result<Regex, PatternError> {
+    let (translated, origins) = match syntax {
+        Syntax::Basic => basic_to_extended(pattern),
+        Syntax::Extended => (pattern.to_string(), Vec::new()),
+        Syntax::Fixed => (regex::escape(pattern), Vec::new()),
+    };
+
+    RegexBuilder::new(&translated)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|err| {
+            // The regex crate only hands back a rendered message, so parse
+            // again to find out where the problem is
+            let parsed = regex_syntax::ParserBuilder::new()
+                .case_insensitive(ignore_case)
+                .build()
+                .parse(&translated);
+            let (message, span) = match parsed {
+                Err(regex_syntax::Error::Parse(err)) => {
+                    (err.kind().to_string(), Some(span_of(err.span())))
+                }
+                Err(regex_syntax::Error::Translate(err)) => {
+                    (err.kind().to_string(), Some(span_of(err.span())))
+                }
+                _ => (err.to_string(), None),
+            };
+            // Spans in a translated pattern are mapped back onto what the
+            // user wrote
+            let span = span.map(|(start, end)| match syntax {
+                Syntax::Basic => (
+                    origins.get(start).map_or(pattern.len(), |origin| origin.0),
+                    match end.checked_sub(1).and_then(|last| origins.get(last)) {
+                        Some(origin) if end > start => origin.1,
+                        _ => origins.get(start).map_or(pattern.len(), |origin| origin.1),
+                    },
+                ),
+                _ => (start, end),
+            });
+            PatternError {
+                message,
+                pattern: pattern.to_string(),
+                span,
+            }
+        })
+}
+
+fn span_of(span: &regex_syntax::ast::Span) -> (usize, usize) {
+    (span.start.offset, span.end.offset)
+}
+
+/// Rewrites a basic regular expression in the syntax of the regex crate.
+/// Alongside the new pattern comes, for every byte of it, the range of the
+/// original pattern that it came from.
+fn basic_to_extended(pattern: &str) -> (String, Vec<(usize, usize)>) {
+    let mut out = String::with_capacity(pattern.len());
+    let mut origins = Vec::with_capacity(pattern.len());
+    let mut push = |out: &mut String, text: &str, start: usize, end: usize| {
+        out.push_str(text);
+        origins.extend(std::iter::repeat_n((start, end), text.len()));
+    };
+
+    // A '*' is literal where there is nothing for it to repeat, and '^' is
+    // only an anchor in the same places
+    let mut at_start = true;
+    let mut chars = pattern.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let was_start = at_start;
+        at_start = false;
+        match c {
+            '\\' => {
+                let Some((_, next)) = chars.next() else {
+                    // A trailing backslash is left for the regex crate to
+                    // complain about
+                    push(&mut out, "\\", start, pattern.len());
+                    break;
+                };
+                let end = start + 1 + next.len_utf8();
+                match next {
+                    '(' | '|' => {
+                        push(&mut out, &next.to_string(), start, end);
+                        at_start = true;
+                    }
+                    ')' | '{' | '}' | '+' | '?' => push(&mut out, &next.to_string(), start, end),
+                    _ => push(&mut out, &pattern[start..end], start, end),
+                }
+            }
+            '(' | ')' | '{' | '}' | '|' | '+' | '?' => {
+                push(&mut out, &format!("\\{c}"), start, start + 1)
+            }
+            '*' if was_start => push(&mut out, "\\*", start, start + 1),
+            '^' if was_start => {
+                push(&mut out, "^", start, start + 1);
+                at_start = true;
+            }
+            '^' => push(&mut out, "\\^", start, start + 1),
+            '$' => {
+                // Only an anchor at the end of the pattern or a group
+                let rest = &pattern[start + 1..];
+                if rest.is_empty() || rest.starts_with("\\)") || rest.starts_with("\\|") {
+                    push(&mut out, "$", start, start + 1);
+                } else {
+                    push(&mut out, "\\$", start, start + 1);
+                }
+            }
+            '[' => {
+                let end = bracket_end(pattern, start);
+                push(
+                    &mut out,
+                    &bracket_to_extended(&pattern[start..end]),
+                    start,
+                    end,
+                );
+                while chars.peek().is_some_and(|&(i, _)| i < end) {
+                    chars.next();
+                }
+            }
+            c => push(
+                &mut out,
+                &pattern[start..start + c.len_utf8()],
+                start,
+                start + c.len_utf8(),
+            ),
+        }
+    }
+    (out, origins)
+}
+
+/// Finds the end of the bracket expression opening at [start]. A ']' right
+/// after the opening '[' or '[^' is a member, not the end. An unclosed
+/// bracket runs to the end of the pattern.
+fn bracket_end(pattern: &str, start: usize) -> usize {
+    let bytes = pattern.as_bytes();
+    let mut i = start + 1;
+    if bytes.get(i) == Some(&b'^') {
+        i += 1;
+    }
+    if bytes.get(i) == Some(&b']') {
+        i += 1;
+    }
+    while i < bytes.len() {
+        match bytes[i] {
+            // Character classes like [:alpha:] may hold a ']' of their own
+            b'[' if bytes.get(i + 1) == Some(&b':') => {
+                i = pattern[i + 2..]
+                    .find(":]")
+                    .map_or(bytes.len(), |at| i + 2 + at + 2);
+            }
+            b']' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// Rewrites a POSIX bracket expression, where a backslash is an ordinary
+/// member, for the regex crate, where it escapes the next character.
+fn bracket_to_extended(bracket: &str) -> String {
+    let mut out = String::with_capacity(bracket.len());
+    let mut rest = bracket;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("[:") {
+            let end = rest.find(":]").map_or(rest.len(), |at| at + 2);
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        match c {
+            '\\' | '&' | '~' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '[' if !out.is_empty() => out.push_str("\\["),
+            c => out.push(c),
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, syntax: Syntax, line: &str) -> bool {
+        build(pattern, syntax, false)
+            .unwrap()
+            .is_match(line.as_bytes())
+    }
+
+    #[test]
+    fn basic() {
+        assert!(matches("a.c", Syntax::Basic, "abc"));
+        assert!(matches("a+", Syntax::Basic, "a+"));
+        assert!(!matches("a+", Syntax::Basic, "aa"));
+        assert!(matches("\\(ab\\)\\{2\\}", Syntax::Basic, "abab"));
+        assert!(matches("x\\|y", Syntax::Basic, "y"));
+        assert!(matches("*star", Syntax::Basic, "*star"));
+        assert!(matches("a^b$c", Syntax::Basic, "a^b$c"));
+        assert!(matches("^[]a\\]*$", Syntax::Basic, "]a\\"));
+        assert!(matches("[[:digit:]]x", Syntax::Basic, "7x"));
+    }
+
+    #[test]
+    fn extended_and_fixed() {
+        assert!(matches("(ab){2}", Syntax::Extended, "abab"));
+        assert!(matches("x|y", Syntax::Extended, "y"));
+        assert!(matches("a.c(", Syntax::Fixed, "xa.c("));
+        assert!(!matches("a.c", Syntax::Fixed, "abc"));
+    }
+
+    #[test]
+    fn ignore_case() {
+        assert!(build("RuSt", Syntax::Basic, true)
+            .unwrap()
+            .is_match(b"trust"));
+    }
+
+    #[test]
+    fn error_points_at_problem() {
+        let err = build("ab(c", Syntax::Extended, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid pattern: unclosed group\n    ab(c\n      ^"
+        );
+
+        // The position is given in terms of the basic syntax
+        let err = build("é\\(c", Syntax::Basic, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid pattern: unclosed group\n    é\\(c\n     ^^"
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! Walking directories for -r, honoring the same ignore files as git and
+//! ripgrep: .gitignore, .ignore and .git/info/exclude.
+
+use ignore::{
+    overrides::{Override, OverrideBuilder},
+    types::{Types, TypesBuilder},
+    DirEntry, WalkBuilder,
+};
+
+use crate::Config;
+
+/// The filters that decide which files under a directory get searched
+pub struct Walker {
+    hidden: bool,
+    no_ignore: bool,
+    globs: Vec<String>,
+    types: Types,
+}
+
+impl Walker {
+    /// Checks the --glob and --type filters in [config] up front, so that a
+    /// bad one is reported before anything is searched.
+    pub fn new(config: &Config) -> Result<Walker, ignore::Error> {
+        let mut types = TypesBuilder::new();
+        types.add_defaults();
+        for name in &config.types {
+            types.select(name);
+        }
+        let walker = Walker {
+            hidden: config.hidden,
+            no_ignore: config.no_ignore,
+            globs: config.globs.clone(),
+            types: types.build()?,
+        };
+        walker.overrides(".")?;
+        Ok(walker)
+    }
+
+    /// Globs are matched against paths relative to the directory being
+    /// walked, so they are built for each [root].
+    fn overrides(&self, root: &str) -> Result<Override, ignore::Error> {
+        let mut overrides = OverrideBuilder::new(root);
+        for glob in &self.globs {
+            overrides.add(glob)?;
+        }
+        overrides.build()
+    }
+
+    /// Walks [root], in order of file name if [sorted] is set. Hidden and
+    /// ignored files are left out unless --hidden or --no-ignore say
+    /// otherwise. Ignore files count whether or not [root] is in a git
+    /// repository.
+    pub fn walk(
+        &self,
+        root: &str,
+        sorted: bool,
+    ) -> Result<impl Iterator<Item = Result<DirEntry, ignore::Error>> + '_, ignore::Error> {
+        let mut builder = WalkBuilder::new(root);
+        builder
+            .standard_filters(!self.no_ignore)
+            .hidden(!self.hidden)
+            .require_git(false)
+            .overrides(self.overrides(root)?);
+        if sorted {
+            builder.sort_by_file_name(|a, b| a.cmp(b));
+        }
+        // File types are checked here rather than by the walk, where a file
+        // of the right type would get past the hidden file rule
+        Ok(builder.build().filter(|entry| match entry {
+            Ok(entry)
+                if entry
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_file()) =>
+            {
+                !self.types.matched(entry.path(), false).is_ignore()
+            }
+            _ => true,
+        }))
+    }
+}
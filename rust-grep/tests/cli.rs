@@ -0,0 +1,680 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::{error::Error, fs, path::Path};
+
+type TestResult = Result<(), Box<dyn Error>>;
+
+const PRG: &str = "rust-grep";
+const POEM: &str = "poem.txt";
+const SAMPLE: &str = "sample_1.txt";
+const MISSING: &str = "no-such-file.txt";
+
+/// Runs the program with [args] and no IGNORE_CASE in the environment
+fn grep(args: &[&str]) -> Result<Command, Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(PRG)?;
+    cmd.args(args).env_remove("IGNORE_CASE");
+    Ok(cmd)
+}
+
+#[test]
+fn usage() -> TestResult {
+    grep(&["--help"])?
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Usage:"));
+    Ok(())
+}
+
+#[test]
+fn dies_no_pattern() -> TestResult {
+    grep(&[])?
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("<PATTERN>"));
+    Ok(())
+}
+
+#[test]
+fn one_file() -> TestResult {
+    grep(&["body", POEM])?
+        .assert()
+        .success()
+        .stdout("I'm nobody! Who are you?\nAre you nobody, too?\nHow dreary to be somebody!\n");
+    Ok(())
+}
+
+#[test]
+fn several_files_get_names() -> TestResult {
+    grep(&["--sort=path", "ody", POEM, SAMPLE])?
+        .assert()
+        .success()
+        .stdout(
+            "poem.txt:I'm nobody! Who are you?\n\
+             poem.txt:Are you nobody, too?\n\
+             poem.txt:How dreary to be somebody!\n\
+             sample_1.txt:Somebody once told me.\n",
+        );
+    Ok(())
+}
+
+#[test]
+fn stdin() -> TestResult {
+    grep(&["cheese"])?
+        .write_stdin("Blue cheese\nand yam\n")
+        .assert()
+        .success()
+        .stdout("Blue cheese\n");
+    grep(&["yam", POEM, "-"])?
+        .write_stdin("Blue cheese\nand yam\n")
+        .assert()
+        .success()
+        .stdout("(standard input):and yam\n");
+    Ok(())
+}
+
+#[test]
+fn ignore_case_flag() -> TestResult {
+    for flag in ["-i", "--ignore-case"] {
+        grep(&[flag, "how", POEM])?
+            .assert()
+            .success()
+            .stdout("How dreary to be somebody!\nHow public, like a frog\n");
+    }
+    Ok(())
+}
+
+#[test]
+fn ignore_case_env_is_a_default() -> TestResult {
+    grep(&["how", POEM])?
+        .env("IGNORE_CASE", "1")
+        .assert()
+        .success()
+        .stdout("How dreary to be somebody!\nHow public, like a frog\n");
+    grep(&["--no-ignore-case", "how", POEM])?
+        .env("IGNORE_CASE", "1")
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+#[test]
+fn skips_bad_file() -> TestResult {
+    grep(&["cheese", MISSING, SAMPLE])?
+        .assert()
+        .code(2)
+        .stdout("sample_1.txt:Blue cheese and yam.\n")
+        .stderr(predicate::str::starts_with(format!("rust-grep: {MISSING}: ")));
+    Ok(())
+}
+
+#[test]
+fn basic_regex() -> TestResult {
+    grep(&["^How .*!$", POEM])?
+        .assert()
+        .success()
+        .stdout("How dreary to be somebody!\n");
+    grep(&["\\(frog\\|bog\\)", POEM])?
+        .assert()
+        .success()
+        .stdout("How public, like a frog\nTo an admiring bog!\n");
+    Ok(())
+}
+
+#[test]
+fn extended_regex() -> TestResult {
+    for flag in ["-E", "--extended-regexp"] {
+        grep(&[flag, "(frog|bog)!?$", POEM])?
+            .assert()
+            .success()
+            .stdout("How public, like a frog\nTo an admiring bog!\n");
+    }
+    Ok(())
+}
+
+#[test]
+fn fixed_strings() -> TestResult {
+    for flag in ["-F", "--fixed-strings"] {
+        grep(&[flag, "you?", POEM])?
+            .assert()
+            .success()
+            .stdout("I'm nobody! Who are you?\n");
+    }
+    Ok(())
+}
+
+#[test]
+fn dies_bad_pattern() -> TestResult {
+    grep(&["-E", "(frog|bog", POEM])?
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "invalid pattern: unclosed group\n    (frog|bog\n    ^\n",
+        ));
+    Ok(())
+}
+
+#[test]
+fn dies_extended_and_fixed() -> TestResult {
+    grep(&["-E", "-F", "x", POEM])?
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+#[test]
+fn invalid_utf8() -> TestResult {
+    grep(&["caf."])?
+        .write_stdin(&b"plain\ncaf\xe9 au lait\n\xff\xfe\n"[..])
+        .assert()
+        .code(1)
+        .stdout(&b""[..]);
+    grep(&["au lait"])?
+        .write_stdin(&b"plain\ncaf\xe9 au lait\n\xff\xfe\n"[..])
+        .assert()
+        .success()
+        .stdout(&b"caf\xe9 au lait\n"[..]);
+    Ok(())
+}
+
+#[test]
+fn last_line_without_newline() -> TestResult {
+    grep(&["end"])?
+        .write_stdin("start\nthe end")
+        .assert()
+        .success()
+        .stdout("the end\n");
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn reports_read_error() -> TestResult {
+    grep(&["cheese", "src", SAMPLE])?
+        .assert()
+        .code(2)
+        .stdout("sample_1.txt:Blue cheese and yam.\n")
+        .stderr("rust-grep: src: Is a directory (os error 21)\n");
+    Ok(())
+}
+
+#[test]
+fn exit_codes() -> TestResult {
+    grep(&["frog", POEM])?.assert().code(0);
+    grep(&["toad", POEM])?.assert().code(1);
+    grep(&["toad", MISSING])?.assert().code(2);
+    grep(&["-E", "(", POEM])?.assert().code(2);
+    grep(&["--no-such-flag", "x", POEM])?.assert().code(2);
+    Ok(())
+}
+
+#[test]
+fn invert_match() -> TestResult {
+    grep(&["-v", "o", POEM])?.assert().success().stdout("\n");
+    grep(&["-v", "e", SAMPLE])?.assert().code(1).stdout("");
+    Ok(())
+}
+
+#[test]
+fn count() -> TestResult {
+    grep(&["-c", "ody", POEM])?.assert().success().stdout("3\n");
+    grep(&["--sort=path", "--count", "-v", "ody", POEM, SAMPLE])?
+        .assert()
+        .success()
+        .stdout("poem.txt:6\nsample_1.txt:2\n");
+    grep(&["-c", "toad", POEM])?.assert().code(1).stdout("0\n");
+    Ok(())
+}
+
+#[test]
+fn files_with_matches() -> TestResult {
+    grep(&["--sort=path", "-l", "cheese", POEM, SAMPLE, "-"])?
+        .write_stdin("more cheese\n")
+        .assert()
+        .success()
+        .stdout("sample_1.txt\n(standard input)\n");
+    Ok(())
+}
+
+#[test]
+fn files_without_match() -> TestResult {
+    // The exit status tells whether a line was selected, as in GNU grep
+    grep(&["-L", "cheese", POEM, SAMPLE])?
+        .assert()
+        .success()
+        .stdout("poem.txt\n");
+    grep(&["-L", "o", POEM, SAMPLE])?
+        .assert()
+        .success()
+        .stdout("");
+    grep(&["-L", "zzz", POEM])?
+        .assert()
+        .code(1)
+        .stdout("poem.txt\n");
+    Ok(())
+}
+
+#[test]
+fn line_number_and_byte_offset() -> TestResult {
+    grep(&["-n", "frog", POEM])?
+        .assert()
+        .success()
+        .stdout("7:How public, like a frog\n");
+    grep(&["-nb", "cheese", POEM, SAMPLE])?
+        .assert()
+        .success()
+        .stdout("sample_1.txt:3:69:Blue cheese and yam.\n");
+    Ok(())
+}
+
+#[test]
+fn only_matching() -> TestResult {
+    grep(&["-o", "-E", "[a-z]*body", POEM])?
+        .assert()
+        .success()
+        .stdout("nobody\nnobody\nsomebody\n");
+    grep(&["-ob", "cheese", SAMPLE])?
+        .assert()
+        .success()
+        .stdout("74:cheese\n");
+    Ok(())
+}
+
+#[test]
+fn quiet() -> TestResult {
+    for flag in ["-q", "--quiet", "--silent"] {
+        grep(&[flag, "frog", POEM])?.assert().code(0).stdout("");
+    }
+    grep(&["-q", "toad", POEM])?.assert().code(1).stdout("");
+    // A match is enough for success, even when another file is missing,
+    // though the missing file is still reported
+    grep(&["--sort=path", "-q", "frog", MISSING, POEM])?
+        .assert()
+        .code(0)
+        .stdout("")
+        .stderr(predicate::str::starts_with(format!("rust-grep: {MISSING}: ")));
+    Ok(())
+}
+
+#[test]
+fn max_count() -> TestResult {
+    grep(&["-m", "2", "o", POEM])?
+        .assert()
+        .success()
+        .stdout("I'm nobody! Who are you?\nAre you nobody, too?\n");
+    grep(&["--sort=path", "-c", "--max-count", "1", "o", POEM, SAMPLE])?
+        .assert()
+        .success()
+        .stdout("poem.txt:1\nsample_1.txt:1\n");
+    grep(&["-m", "0", "o", POEM])?.assert().code(1).stdout("");
+    Ok(())
+}
+
+#[test]
+fn after_context() -> TestResult {
+    grep(&["-A", "1", "-n", "tell", POEM])?
+        .assert()
+        .success()
+        .stdout(
+            "3:Then there's a pair of us - don't tell!\n\
+             4-They'd banish us, you know.\n\
+             --\n\
+             8:To tell your name the livelong day\n\
+             9-To an admiring bog!\n",
+        );
+    Ok(())
+}
+
+#[test]
+fn before_context() -> TestResult {
+    grep(&["--before-context", "1", "-E", "frog|too"])?
+        .write_stdin("one\ntoo\nthree\nfour\nfive\nfrog\n")
+        .assert()
+        .success()
+        .stdout("one\ntoo\n--\nfive\nfrog\n");
+    Ok(())
+}
+
+#[test]
+fn context_around() -> TestResult {
+    grep(&["-C", "1", "-n", "frog", POEM])?
+        .assert()
+        .success()
+        .stdout(
+            "6-How dreary to be somebody!\n\
+             7:How public, like a frog\n\
+             8-To tell your name the livelong day\n",
+        );
+    // -B wins over -C for the lines before
+    grep(&["-C", "2", "-B", "0", "frog", POEM])?
+        .assert()
+        .success()
+        .stdout(
+            "How public, like a frog\n\
+             To tell your name the livelong day\n\
+             To an admiring bog!\n",
+        );
+    Ok(())
+}
+
+#[test]
+fn context_across_files() -> TestResult {
+    grep(&["--sort=path", "-B", "1", "-n", "e", SAMPLE, POEM])?
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "sample_1.txt:1:The quick brown fox jumped over the lazy dog.\n\
+             sample_1.txt:2:Somebody once told me.\n\
+             sample_1.txt:3:Blue cheese and yam.\n\
+             --\n\
+             poem.txt:1:I'm nobody! Who are you?\n",
+        ));
+    Ok(())
+}
+
+#[test]
+fn context_after_max_count() -> TestResult {
+    // The trailing context of the last line is still printed
+    grep(&["-m", "1", "-A", "1", "nobody", POEM])?
+        .assert()
+        .success()
+        .stdout("I'm nobody! Who are you?\nAre you nobody, too?\n");
+    Ok(())
+}
+
+/// Builds a small project to search with -r, with a file caught by each of
+/// the filters
+fn gen_tree() -> Result<tempfile::TempDir, Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let files: &[(&str, &[u8])] = &[
+        (".gitignore", b"target/\n*.log\n"),
+        (".ignore", b"skip.txt\n"),
+        (".git/info/exclude", b"excluded.rs\n"),
+        (".hidden.rs", b"needle hidden\n"),
+        ("app.log", b"needle logged\n"),
+        ("data.bin", b"needle\0binary\n"),
+        ("excluded.rs", b"needle excluded\n"),
+        ("notes.md", b"needle in the notes\n"),
+        ("skip.txt", b"needle skipped\n"),
+        ("src/main.rs", b"fn main() {}\nfn needle() {}\n"),
+        ("target/out.rs", b"needle built\n"),
+    ];
+    for (name, content) in files {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, content)?;
+    }
+    Ok(dir)
+}
+
+/// Runs the program with [args] from inside [dir]
+fn grep_in(dir: &Path, args: &[&str]) -> Result<Command, Box<dyn Error>> {
+    let mut cmd = grep(args)?;
+    cmd.current_dir(dir);
+    Ok(cmd)
+}
+
+#[test]
+fn recursive() -> TestResult {
+    let dir = gen_tree()?;
+    grep_in(dir.path(), &["-r", "--sort=path", "needle"])?
+        .assert()
+        .success()
+        .stdout("notes.md:needle in the notes\nsrc/main.rs:fn needle() {}\n");
+    grep_in(
+        dir.path(),
+        &["--recursive", "--sort=path", "-n", "needle", "."],
+    )?
+    .assert()
+    .success()
+    .stdout("./notes.md:1:needle in the notes\n./src/main.rs:2:fn needle() {}\n");
+    Ok(())
+}
+
+#[test]
+fn recursive_named_file() -> TestResult {
+    let dir = gen_tree()?;
+    // Files given by name are searched whatever the filters say
+    grep_in(dir.path(), &["-r", "needle", "app.log"])?
+        .assert()
+        .success()
+        .stdout("needle logged\n");
+    Ok(())
+}
+
+#[test]
+fn recursive_hidden_and_no_ignore() -> TestResult {
+    let dir = gen_tree()?;
+    grep_in(dir.path(), &["-rl", "--sort=path", "--hidden", "needle"])?
+        .assert()
+        .success()
+        .stdout(".hidden.rs\nnotes.md\nsrc/main.rs\n");
+    grep_in(dir.path(), &["-rl", "--sort=path", "--no-ignore", "needle"])?
+        .assert()
+        .success()
+        .stdout("app.log\nexcluded.rs\nnotes.md\nskip.txt\nsrc/main.rs\ntarget/out.rs\n");
+    Ok(())
+}
+
+#[test]
+fn recursive_glob_and_type() -> TestResult {
+    let dir = gen_tree()?;
+    grep_in(
+        dir.path(),
+        &["-rl", "--sort=path", "--glob", "*.md", "needle"],
+    )?
+    .assert()
+    .success()
+    .stdout("notes.md\n");
+    grep_in(dir.path(), &["-rl", "--sort=path", "-g", "!*.md", "needle"])?
+        .assert()
+        .success()
+        .stdout("src/main.rs\n");
+    grep_in(
+        dir.path(),
+        &[
+            "-rl",
+            "--sort=path",
+            "--type",
+            "rust",
+            "--no-ignore",
+            "needle",
+        ],
+    )?
+    .assert()
+    .success()
+    .stdout("excluded.rs\nsrc/main.rs\ntarget/out.rs\n");
+    Ok(())
+}
+
+#[test]
+fn dies_unknown_type() -> TestResult {
+    grep(&["-r", "-t", "nosuchtype", "needle", "."])?
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("nosuchtype"));
+    Ok(())
+}
+
+/// Builds a tree of [count] files, each with the same three matching lines
+fn gen_many(count: usize) -> Result<tempfile::TempDir, Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    for i in 0..count {
+        let path = dir.path().join(format!("d{}/f{:03}.txt", i % 4, i));
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, "needle 1\nhay\nneedle 2\nneedle 3\n")?;
+    }
+    Ok(dir)
+}
+
+#[test]
+fn parallel_sorted_matches_sequential() -> TestResult {
+    let dir = gen_many(60)?;
+    let serial = grep_in(dir.path(), &["-r", "-n", "-j", "1", "needle"])?.output()?;
+    assert!(serial.status.success());
+    for threads in ["2", "8"] {
+        grep_in(
+            dir.path(),
+            &["-r", "-n", "-j", threads, "--sort", "path", "needle"],
+        )?
+        .assert()
+        .success()
+        .stdout(serial.stdout.clone());
+    }
+    Ok(())
+}
+
+#[test]
+fn parallel_keeps_files_together() -> TestResult {
+    let dir = gen_many(60)?;
+    let output = grep_in(dir.path(), &["-r", "-j", "8", "-A", "1", "needle"])?.output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+
+    // Every file's lines come as one block, and blocks are split by "--"
+    let mut seen = Vec::new();
+    for block in stdout.split("--\n") {
+        let names: Vec<&str> = block
+            .lines()
+            .map(|line| line.split([':', '-']).next().unwrap())
+            .collect();
+        assert_eq!(names.len(), 4);
+        assert!(names.iter().all(|name| *name == names[0]));
+        seen.push(names[0]);
+    }
+    seen.sort();
+    seen.dedup();
+    assert_eq!(seen.len(), 60);
+    Ok(())
+}
+
+#[test]
+fn parallel_large_files() -> TestResult {
+    // Each file has more output than may be held back while waiting
+    let dir = tempfile::tempdir()?;
+    let lines: String = (0..20_000).map(|n| format!("needle {n}\n")).collect();
+    for i in 0..6 {
+        fs::write(dir.path().join(format!("f{i}.txt")), &lines)?;
+    }
+    let serial = grep_in(dir.path(), &["-r", "-j", "1", "needle"])?.output()?;
+    grep_in(dir.path(), &["-r", "-j", "3", "--sort", "path", "needle"])?
+        .assert()
+        .success()
+        .stdout(serial.stdout.clone());
+
+    // Unsorted, each file's lines still come out in one piece and in order
+    let output = grep_in(dir.path(), &["-r", "-j", "3", "needle"])?.output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    let mut blocks: Vec<&str> = Vec::new();
+    for line in stdout.lines() {
+        let (name, _) = line.split_once(':').unwrap();
+        if blocks.last() != Some(&name) {
+            blocks.push(name);
+        }
+    }
+    assert_eq!(blocks.len(), 6);
+    let mut sorted: Vec<&str> = stdout.lines().collect();
+    sorted.sort_by_key(|line| line.split_once(':').unwrap().0);
+    assert_eq!(sorted.join("\n") + "\n", String::from_utf8(serial.stdout)?);
+    Ok(())
+}
+
+#[test]
+fn parallel_quiet_and_count() -> TestResult {
+    let dir = gen_many(20)?;
+    grep_in(dir.path(), &["-rq", "-j", "4", "needle"])?
+        .assert()
+        .code(0)
+        .stdout("");
+    let output = grep_in(dir.path(), &["-rc", "-j", "4", "needle"])?.output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    assert_eq!(stdout.lines().count(), 20);
+    assert!(stdout.lines().all(|line| line.ends_with(".txt:3")));
+    Ok(())
+}
+
+#[test]
+fn dies_zero_threads() -> TestResult {
+    grep(&["-j", "0", "needle", POEM])?
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("invalid value '0'"));
+    Ok(())
+}
+
+#[test]
+fn color_never_and_piped() -> TestResult {
+    for args in [&["frog", POEM][..], &["--color=never", "frog", POEM][..]] {
+        grep(args)?
+            .assert()
+            .success()
+            .stdout("How public, like a frog\n");
+    }
+    Ok(())
+}
+
+#[test]
+fn color_always() -> TestResult {
+    grep(&[
+        "--color=always",
+        "-n",
+        "o",
+        SAMPLE,
+        POEM,
+        "--sort=path",
+        "-m",
+        "1",
+    ])?
+    .assert()
+    .success()
+    .stdout(
+        "\x1b[35m\x1b[Ksample_1.txt\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[K\
+             \x1b[32m\x1b[K1\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[K\
+             The quick br\x1b[01;31m\x1b[Ko\x1b[m\x1b[Kwn f\x1b[01;31m\x1b[Ko\x1b[m\x1b[Kx \
+             jumped \x1b[01;31m\x1b[Ko\x1b[m\x1b[Kver the lazy d\x1b[01;31m\x1b[Ko\x1b[m\x1b[Kg.\n\
+             \x1b[35m\x1b[Kpoem.txt\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[K\
+             \x1b[32m\x1b[K1\x1b[m\x1b[K\x1b[36m\x1b[K:\x1b[m\x1b[K\
+             I'm n\x1b[01;31m\x1b[Ko\x1b[m\x1b[Kb\x1b[01;31m\x1b[Ko\x1b[m\x1b[Kdy! Wh\
+             \x1b[01;31m\x1b[Ko\x1b[m\x1b[K are y\x1b[01;31m\x1b[Ko\x1b[m\x1b[Ku?\n",
+    );
+    Ok(())
+}
+
+#[test]
+fn grep_colors() -> TestResult {
+    grep(&["--colour=always", "-o", "-C", "1", "-E", "fro?g", POEM])?
+        .env("GREP_COLORS", "ms=04:se=")
+        .assert()
+        .success()
+        .stdout("\x1b[04m\x1b[Kfrog\x1b[m\x1b[K\n");
+    grep(&["--color=always", "-A", "1", "-E", "nobody,|public", POEM])?
+        .env("GREP_COLORS", "ms=:se=33")
+        .assert()
+        .success()
+        .stdout(
+            "Are you nobody, too?\n\
+             Then there's a pair of us - don't tell!\n\
+             \x1b[33m\x1b[K--\x1b[m\x1b[K\n\
+             How public, like a frog\n\
+             To tell your name the livelong day\n",
+        );
+    Ok(())
+}
+
+#[test]
+fn color_file_names() -> TestResult {
+    grep(&[
+        "--color=always",
+        "--sort=path",
+        "-l",
+        "cheese",
+        POEM,
+        SAMPLE,
+    ])?
+    .env("GREP_COLORS", "fn=34")
+    .assert()
+    .success()
+    .stdout("\x1b[34m\x1b[Ksample_1.txt\x1b[m\x1b[K\n");
+    Ok(())
+}